@@ -3,8 +3,9 @@ use std::path::Path;
 
 mod build_utils;
 use build_utils::{
-    collect_schema_files, generate_action_routing, generate_actions_mod_rs, generate_embedded_code,
-    generate_trigger_routing, generate_triggers_mod_rs,
+    collect_schema_files, generate_action_routing, generate_actions_mod_rs,
+    generate_connection_profile, generate_embedded_code, generate_trigger_routing,
+    generate_triggers_mod_rs,
 };
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -12,6 +13,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("cargo:rerun-if-changed=src/actions");
     println!("cargo:rerun-if-changed=src/triggers");
     println!("cargo:rerun-if-changed=build_templates");
+    println!("cargo:rerun-if-changed=connection.toml");
+    println!("cargo:rerun-if-changed=connection.yaml");
+    println!("cargo:rerun-if-changed=connection.yml");
 
     let generated_dir = Path::new("src/schemas/generated");
     fs::create_dir_all(generated_dir)?;
@@ -34,6 +38,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Generate dynamic trigger routing
     generate_trigger_routing(generated_dir)?;
 
+    // Embed the connection profile
+    generate_connection_profile(generated_dir)?;
+
     println!("cargo:warning=Build artifacts generated successfully");
     Ok(())
 }
@@ -4,6 +4,7 @@ pub enum BuildError {
     Io(std::io::Error),
     Json(serde_json::Error),
     Template(String),
+    Profile(String),
 }
 
 impl std::fmt::Display for BuildError {
@@ -12,6 +13,7 @@ impl std::fmt::Display for BuildError {
             BuildError::Io(e) => write!(f, "IO error: {}", e),
             BuildError::Json(e) => write!(f, "JSON error: {}", e),
             BuildError::Template(msg) => write!(f, "Template error: {}", msg),
+            BuildError::Profile(msg) => write!(f, "Connection profile error: {}", msg),
         }
     }
 }
@@ -0,0 +1,112 @@
+use super::BuildError;
+use std::fs;
+use std::path::Path;
+
+/// Profile files, in the order they are looked for
+const PROFILE_FILES: [&str; 3] = ["connection.toml", "connection.yaml", "connection.yml"];
+
+/// Keys a connection profile may contain
+const PROFILE_KEYS: [&str; 7] = [
+    "base_url",
+    "base_url_path",
+    "headers_path",
+    "headers",
+    "default_headers",
+    "auth",
+    "rate_limit",
+];
+
+/// Read the connection profile, if the connector has one, and convert it to JSON
+pub fn read_connection_profile() -> Result<Option<serde_json::Value>, BuildError> {
+    let Some(profile_file) = PROFILE_FILES.iter().find(|file| Path::new(file).exists()) else {
+        return Ok(None);
+    };
+
+    let content = fs::read_to_string(profile_file)?;
+    let profile: serde_json::Value = if profile_file.ends_with(".toml") {
+        let value: toml::Value = toml::from_str(&content)
+            .map_err(|e| BuildError::Profile(format!("{}: {}", profile_file, e)))?;
+        serde_json::to_value(value)?
+    } else {
+        serde_yaml::from_str(&content)
+            .map_err(|e| BuildError::Profile(format!("{}: {}", profile_file, e)))?
+    };
+
+    let Some(table) = profile.as_object() else {
+        return Err(BuildError::Profile(format!(
+            "{}: the profile must be a table of settings",
+            profile_file
+        )));
+    };
+
+    // Catch typos early instead of silently falling back to the defaults
+    for key in table.keys() {
+        if !PROFILE_KEYS.contains(&key.as_str()) {
+            return Err(BuildError::Profile(format!(
+                "{}: unknown setting '{}', expected one of: {}",
+                profile_file,
+                key,
+                PROFILE_KEYS.join(", ")
+            )));
+        }
+    }
+
+    // A wrong type would only surface as an error on the first request
+    for (key, value) in table {
+        let expected = match key.as_str() {
+            "base_url" | "base_url_path" | "headers_path" if !value.is_string() => "a string",
+            "headers" | "default_headers" if !is_string_table(value) => "a table of strings",
+            "auth" | "rate_limit" if !value.is_object() => "a table",
+            _ => continue,
+        };
+        return Err(BuildError::Profile(format!(
+            "{}: {} must be {}, got {}",
+            profile_file, key, expected, value
+        )));
+    }
+
+    // `base_url` is an RFC 6570 template, unlike the `{{path}}` headers and auth
+    if let Some(base_url) = table.get("base_url").and_then(|v| v.as_str())
+        && base_url.contains("{{")
+    {
+        return Err(BuildError::Profile(format!(
+            "{}: base_url is a URI template with single-brace variables, \
+             e.g. https://{{subdomain}}.example.com, not {{{{path}}}} placeholders: {}",
+            profile_file, base_url
+        )));
+    }
+
+    Ok(Some(profile))
+}
+
+fn is_string_table(value: &serde_json::Value) -> bool {
+    value
+        .as_object()
+        .is_some_and(|table| table.values().all(|value| value.is_string()))
+}
+
+/// Generate the embedded connection profile code
+pub fn generate_connection_profile(out_dir: &Path) -> Result<(), BuildError> {
+    let profile_json = match read_connection_profile()? {
+        Some(profile) => serde_json::to_string(&profile)?,
+        None => String::new(),
+    };
+
+    // Read the template
+    let template =
+        fs::read_to_string("build_templates/connection_profile.rs.template").map_err(|e| {
+            BuildError::Template(format!(
+                "Failed to read connection_profile.rs.template: {}",
+                e
+            ))
+        })?;
+
+    // Replace placeholders
+    let profile_code =
+        template.replace("{CONNECTION_PROFILE_JSON}", &format!("{:?}", profile_json));
+
+    let profile_file = out_dir.join("connection_profile.rs");
+    fs::write(profile_file, profile_code)?;
+
+    Ok(())
+}
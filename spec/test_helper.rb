@@ -84,6 +84,18 @@ module TestHelper
       configure_endpoint(mock_config)
     end
 
+    # Stand-in for an OAuth2 token endpoint, for connections using the `oauth2` block
+    def mock_oauth2_token_endpoint(path = '/oauth/token', access_token: 'test_access_token',
+                                   expires_in: 3600, status: 200)
+      response_body = if status == 200
+                        { "access_token" => access_token, "token_type" => "Bearer", "expires_in" => expires_in }
+                      else
+                        { "error" => "invalid_client" }
+                      end
+
+      mock_endpoint(:post, path, response_body, status: status)
+    end
+
     def clear_endpoints
       uri = URI("#{@base_url}/__admin/mappings")
       http = Net::HTTP.new(uri.host, uri.port)
@@ -217,4 +229,17 @@ module TestHelper
       }
     }
   end
+
+  def self.oauth2_connection(grant_type: 'client_credentials')
+    {
+      "base_url" => "http://localhost:8080",
+      "oauth2" => {
+        "token_url" => "http://localhost:8080/oauth/token",
+        "client_id" => "test_client",
+        "client_secret" => "test_secret",
+        "grant_type" => grant_type,
+        "refresh_token" => "test_refresh_token"
+      }
+    }
+  end
 end
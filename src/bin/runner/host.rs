@@ -0,0 +1,201 @@
+//! Host implementations of the `http`, `environment` and `file` imports
+//!
+//! Requests are sent for real with `ureq`, environment variables come from
+//! the runner's own environment.
+
+use crate::standout::app::{environment, file, http, types};
+use crate::wire;
+use std::time::Duration;
+use wasmtime::component::{Resource, ResourceTable};
+use wasmtime_wasi::{IoView, WasiCtx, WasiView};
+
+/// Per-invocation state of the component
+pub struct Host {
+    table: ResourceTable,
+    wasi: WasiCtx,
+    agent: ureq::Agent,
+}
+
+impl Host {
+    pub fn new(wasi: WasiCtx) -> Self {
+        Host {
+            table: ResourceTable::new(),
+            wasi,
+            agent: wire::agent(Some(Duration::from_secs(30))),
+        }
+    }
+
+    fn send_request(&self, builder: &RequestBuilder) -> Result<http::Response, ureq::Error> {
+        wire::send(
+            &self.agent,
+            builder.method,
+            &builder.url,
+            &builder.headers,
+            builder.body.clone(),
+        )
+    }
+
+    fn builder(&self, builder: &Resource<RequestBuilder>) -> &RequestBuilder {
+        self.table
+            .get(builder)
+            .expect("request-builder handle is valid")
+    }
+
+    fn push(&mut self, builder: RequestBuilder) -> Resource<RequestBuilder> {
+        self.table
+            .push(builder)
+            .expect("resource table has room for a request-builder")
+    }
+}
+
+impl IoView for Host {
+    fn table(&mut self) -> &mut ResourceTable {
+        &mut self.table
+    }
+}
+
+impl WasiView for Host {
+    fn ctx(&mut self) -> &mut WasiCtx {
+        &mut self.wasi
+    }
+}
+
+/// The state behind a `request-builder` resource
+#[derive(Clone)]
+pub struct RequestBuilder {
+    method: http::Method,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl types::Host for Host {}
+
+impl http::Host for Host {}
+
+impl http::HostRequestBuilder for Host {
+    fn new(&mut self) -> Resource<RequestBuilder> {
+        self.push(RequestBuilder {
+            method: http::Method::Get,
+            url: String::new(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        })
+    }
+
+    fn method(
+        &mut self,
+        builder: Resource<RequestBuilder>,
+        method: http::Method,
+    ) -> Resource<RequestBuilder> {
+        let builder = RequestBuilder {
+            method,
+            ..self.builder(&builder).clone()
+        };
+        self.push(builder)
+    }
+
+    fn url(&mut self, builder: Resource<RequestBuilder>, url: String) -> Resource<RequestBuilder> {
+        let builder = RequestBuilder {
+            url,
+            ..self.builder(&builder).clone()
+        };
+        self.push(builder)
+    }
+
+    fn header(
+        &mut self,
+        builder: Resource<RequestBuilder>,
+        key: String,
+        value: String,
+    ) -> Resource<RequestBuilder> {
+        let mut builder = self.builder(&builder).clone();
+        builder.headers.push((key, value));
+        self.push(builder)
+    }
+
+    fn headers(
+        &mut self,
+        builder: Resource<RequestBuilder>,
+        headers: Vec<(String, String)>,
+    ) -> Resource<RequestBuilder> {
+        let mut builder = self.builder(&builder).clone();
+        builder.headers.extend(headers);
+        self.push(builder)
+    }
+
+    fn body(
+        &mut self,
+        builder: Resource<RequestBuilder>,
+        body: String,
+    ) -> Resource<RequestBuilder> {
+        self.body_bytes(builder, body.into_bytes())
+    }
+
+    fn body_bytes(
+        &mut self,
+        builder: Resource<RequestBuilder>,
+        body: Vec<u8>,
+    ) -> Resource<RequestBuilder> {
+        let builder = RequestBuilder {
+            body,
+            ..self.builder(&builder).clone()
+        };
+        self.push(builder)
+    }
+
+    fn object(&mut self, builder: Resource<RequestBuilder>) -> http::Request {
+        let builder = self.builder(&builder);
+        http::Request {
+            method: builder.method,
+            url: builder.url.clone(),
+            headers: builder.headers.clone(),
+            body: String::from_utf8_lossy(&builder.body).into_owned(),
+        }
+    }
+
+    fn send(
+        &mut self,
+        builder: Resource<RequestBuilder>,
+    ) -> Result<http::Response, http::RequestError> {
+        self.send_request(self.builder(&builder))
+            .map_err(|e| http::RequestError::Other(e.to_string()))
+    }
+
+    fn drop(&mut self, builder: Resource<RequestBuilder>) -> wasmtime::Result<()> {
+        self.table.delete(builder)?;
+        Ok(())
+    }
+}
+
+impl environment::Host for Host {
+    fn env_vars(&mut self) -> Vec<(String, String)> {
+        std::env::vars().collect()
+    }
+
+    fn env_var(&mut self, name: String) -> Option<String> {
+        std::env::var(name).ok()
+    }
+}
+
+impl file::Host for Host {
+    fn normalize(
+        &mut self,
+        source: String,
+        headers: Option<file::Headers>,
+        filename: Option<String>,
+    ) -> Result<file::FileData, file::FileError> {
+        wire::normalize_file(&source, filename, |url| {
+            let builder = RequestBuilder {
+                method: http::Method::Get,
+                url: url.to_string(),
+                headers: headers.unwrap_or_default(),
+                body: Vec::new(),
+            };
+            self.send_request(&builder).map_err(|e| match e {
+                ureq::Error::Timeout(_) => file::FileError::Timeout(e.to_string()),
+                _ => file::FileError::FetchFailed(e.to_string()),
+            })
+        })
+    }
+}
@@ -0,0 +1,283 @@
+//! Runs the built component locally, without the Ruby `app_bridge` gem
+//!
+//! ```text
+//! cargo build --release --target wasm32-wasip2
+//! cargo run --features runner --bin runner -- action-ids
+//! cargo run --features runner --bin runner -- action create_contact \
+//!     --connection connection.json --input input.json
+//! ```
+
+// Shared with the component, see the module docs of `wire`
+#[path = "../../base64.rs"]
+#[allow(dead_code)]
+mod base64;
+mod host;
+#[path = "../../wire.rs"]
+mod wire;
+
+use host::Host;
+use serde_json::{Value, json};
+use standout::app::types::{
+    ActionContext, AppError, Connection, ErrorCode, TriggerContext, TriggerResponse,
+};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use wasmtime::component::{Component, Linker};
+use wasmtime::{Engine, Store};
+use wasmtime_wasi::WasiCtxBuilder;
+
+wasmtime::component::bindgen!({
+    path: "wit/standout-app.wit",
+    world: "bridge",
+    with: {
+        "standout:app/http/request-builder": host::RequestBuilder,
+    },
+});
+
+const USAGE: &str = "\
+Usage: runner [--component <file.wasm>] <command>
+
+Commands:
+  action-ids                         List the action IDs
+  trigger-ids                        List the trigger IDs
+  action-schema <id>                 Print the input and output schemas of an action
+  trigger-schema <id>                Print the input and output schemas of a trigger
+  action <id>                        Execute an action
+  trigger <id>                       Fetch the events of a trigger
+
+Options:
+  --component <file.wasm>            Defaults to the release build in target/wasm32-wasip2
+  --connection <file.json>           Connection data (defaults to {})
+  --input <file.json>                Action or trigger input (defaults to {})
+  --store <file>                     Trigger store from the previous run (defaults to empty)
+";
+
+/// The parsed command line
+struct Args {
+    component: PathBuf,
+    command: String,
+    id: Option<String>,
+    connection: Option<PathBuf>,
+    input: Option<PathBuf>,
+    store: Option<PathBuf>,
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) if message.is_empty() => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(&args) {
+        Ok(Ok(output)) => {
+            println!("{}", pretty(&output));
+            ExitCode::SUCCESS
+        }
+        Ok(Err(err)) => {
+            eprintln!("{}", pretty(&app_error_json(&err)));
+            ExitCode::FAILURE
+        }
+        Err(err) => {
+            eprintln!("Error: {:#}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        component: default_component(),
+        command: String::new(),
+        id: None,
+        connection: None,
+        input: None,
+        store: None,
+    };
+    let mut positional = Vec::new();
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .map(PathBuf::from)
+                .ok_or_else(|| format!("{} needs a value", name))
+        };
+
+        match arg.as_str() {
+            "--component" => parsed.component = value(&arg)?,
+            "--connection" => parsed.connection = Some(value(&arg)?),
+            "--input" => parsed.input = Some(value(&arg)?),
+            "--store" => parsed.store = Some(value(&arg)?),
+            "-h" | "--help" => return Err(String::new()),
+            flag if flag.starts_with("--") => return Err(format!("Unknown option {}", flag)),
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    parsed.command = positional.next().ok_or("Missing command")?;
+    parsed.id = positional.next();
+    if let Some(extra) = positional.next() {
+        return Err(format!("Unexpected argument {}", extra));
+    }
+
+    let needs_id = match parsed.command.as_str() {
+        "action-ids" | "trigger-ids" => false,
+        "action-schema" | "trigger-schema" | "action" | "trigger" => true,
+        command => return Err(format!("Unknown command {}", command)),
+    };
+    if needs_id && parsed.id.is_none() {
+        return Err(format!("{} needs an ID", parsed.command));
+    }
+
+    Ok(parsed)
+}
+
+/// `target/wasm32-wasip2/release/<crate>.wasm`
+fn default_component() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("target/wasm32-wasip2/release")
+        .join(format!("{}.wasm", env!("CARGO_PKG_NAME").replace('-', "_")))
+}
+
+/// Instantiate the component and run the command
+fn run(args: &Args) -> wasmtime::Result<Result<Value, AppError>> {
+    let engine = Engine::default();
+    let component = Component::from_file(&engine, &args.component).map_err(|e| {
+        e.context(format!(
+            "Failed to load {} (build it with `cargo build --release --target wasm32-wasip2`)",
+            args.component.display()
+        ))
+    })?;
+
+    let mut linker = Linker::<Host>::new(&engine);
+    wasmtime_wasi::add_to_linker_sync(&mut linker)?;
+    Bridge::add_to_linker(&mut linker, |host| host)?;
+
+    let wasi = WasiCtxBuilder::new().inherit_stderr().build();
+    let mut store = Store::new(&engine, Host::new(wasi));
+    let bridge = Bridge::instantiate(&mut store, &component, &linker)?;
+    let actions = bridge.standout_app_actions();
+    let triggers = bridge.standout_app_triggers();
+
+    let id = args.id.clone().unwrap_or_default();
+    let connection = Connection {
+        id: "local".to_string(),
+        name: "Local connection".to_string(),
+        serialized_data: read_json(args.connection.as_ref())?,
+    };
+    let serialized_input = read_json(args.input.as_ref())?;
+
+    let action_context = || ActionContext {
+        action_id: id.clone(),
+        connection: connection.clone(),
+        serialized_input: serialized_input.clone(),
+        reference_object: None,
+    };
+    let trigger_context = || -> wasmtime::Result<TriggerContext> {
+        Ok(TriggerContext {
+            trigger_id: id.clone(),
+            connection: connection.clone(),
+            store: match &args.store {
+                Some(path) => read_file(path)?,
+                None => String::new(),
+            },
+            serialized_input: serialized_input.clone(),
+        })
+    };
+
+    Ok(match args.command.as_str() {
+        "action-ids" => actions.call_action_ids(&mut store)?.map(|ids| json!(ids)),
+        "trigger-ids" => triggers.call_trigger_ids(&mut store)?.map(|ids| json!(ids)),
+        "action-schema" => {
+            let context = action_context();
+            let input = actions.call_input_schema(&mut store, &context)?;
+            let output = actions.call_output_schema(&mut store, &context)?;
+            input.and_then(|input| output.map(|output| schemas(&input, &output)))
+        }
+        "trigger-schema" => {
+            let context = trigger_context()?;
+            let input = triggers.call_input_schema(&mut store, &context)?;
+            let output = triggers.call_output_schema(&mut store, &context)?;
+            input.and_then(|input| output.map(|output| schemas(&input, &output)))
+        }
+        "action" => actions
+            .call_execute(&mut store, &action_context())?
+            .map(|response| parse(&response.serialized_output)),
+        "trigger" => triggers
+            .call_fetch_events(&mut store, &trigger_context()?)?
+            .map(|response| trigger_response_json(&response)),
+        command => unreachable!("parse_args accepted command {}", command),
+    })
+}
+
+fn read_file(path: &PathBuf) -> wasmtime::Result<String> {
+    std::fs::read_to_string(path)
+        .map_err(|e| wasmtime::Error::new(e).context(format!("Failed to read {}", path.display())))
+}
+
+/// The file's JSON, compacted, or `{}` without a file
+fn read_json(path: Option<&PathBuf>) -> wasmtime::Result<String> {
+    let Some(path) = path else {
+        return Ok("{}".to_string());
+    };
+
+    let value: Value = serde_json::from_str(&read_file(path)?).map_err(|e| {
+        wasmtime::Error::new(e).context(format!("Invalid JSON in {}", path.display()))
+    })?;
+    Ok(value.to_string())
+}
+
+/// Parse serialized JSON from the component, keeping it as a string if it is not JSON
+fn parse(serialized: &str) -> Value {
+    serde_json::from_str(serialized).unwrap_or_else(|_| json!(serialized))
+}
+
+fn schemas(input: &str, output: &str) -> Value {
+    json!({ "input": parse(input), "output": parse(output) })
+}
+
+fn trigger_response_json(response: &TriggerResponse) -> Value {
+    let events = response
+        .events
+        .iter()
+        .map(|event| json!({ "id": event.id, "data": parse(&event.serialized_data) }))
+        .collect::<Vec<_>>();
+
+    json!({ "events": events, "store": parse(&response.store) })
+}
+
+fn app_error_json(err: &AppError) -> Value {
+    let code = match &err.code {
+        ErrorCode::Unauthenticated => json!("unauthenticated"),
+        ErrorCode::Forbidden => json!("forbidden"),
+        ErrorCode::Misconfigured => json!("misconfigured"),
+        ErrorCode::Unsupported => json!("unsupported"),
+        ErrorCode::RateLimit => json!("rate-limit"),
+        ErrorCode::Timeout => json!("timeout"),
+        ErrorCode::Unavailable => json!("unavailable"),
+        ErrorCode::InternalError => json!("internal-error"),
+        ErrorCode::MalformedResponse => json!("malformed-response"),
+        ErrorCode::Other => json!("other"),
+        ErrorCode::RetryWithReference(reference) => json!({
+            "retry-with-reference": {
+                "reference": reference.reference,
+                "status": reference.status,
+            }
+        }),
+        ErrorCode::CompleteWorkflow => json!("complete-workflow"),
+        ErrorCode::CompleteParent => json!("complete-parent"),
+    };
+
+    json!({ "error": { "code": code, "message": err.message } })
+}
+
+fn pretty(value: &Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+}
@@ -0,0 +1,209 @@
+use super::{ApiResponse, Multipart};
+use crate::standout::app::{
+    http::RequestBuilder,
+    types::{AppError, ErrorCode},
+};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+
+/// A request body and the encoding it is sent with
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub enum RequestBody {
+    /// Serialized as `application/json`
+    Json(Value),
+    /// Encoded as `application/x-www-form-urlencoded`
+    Form(Vec<(String, String)>),
+    /// Sent as is, `text/plain` unless another content type is given
+    Text {
+        content: String,
+        content_type: String,
+    },
+    /// Sent through `body-bytes` with the given content type
+    Bytes { data: Vec<u8>, content_type: String },
+    /// Encoded as `multipart/form-data` and sent through `body-bytes`
+    Multipart(Multipart),
+}
+
+impl RequestBody {
+    /// The value for the `Content-Type` header
+    pub fn content_type(&self) -> &str {
+        match self {
+            RequestBody::Json(_) => "application/json",
+            RequestBody::Form(_) => "application/x-www-form-urlencoded",
+            RequestBody::Text { content_type, .. } | RequestBody::Bytes { content_type, .. } => {
+                content_type
+            }
+            RequestBody::Multipart(form) => form.content_type(),
+        }
+    }
+
+    /// Set the encoded body on a request builder
+    pub fn apply(&self, request_builder: RequestBuilder) -> Result<RequestBuilder, AppError> {
+        Ok(match self {
+            RequestBody::Json(value) => {
+                let body_str = serde_json::to_string(value).map_err(|e| AppError {
+                    code: ErrorCode::Other,
+                    message: format!("Failed to serialize JSON body: {}", e),
+                })?;
+                request_builder.body(&body_str)
+            }
+            RequestBody::Form(fields) => request_builder.body(&encode_form(fields)),
+            RequestBody::Text { content, .. } => request_builder.body(content),
+            RequestBody::Bytes { data, .. } => request_builder.body_bytes(data),
+            RequestBody::Multipart(form) => request_builder.body_bytes(&form.encode()),
+        })
+    }
+}
+
+/// Turns a response body into JSON for the content types it handles
+///
+/// Register custom decoders with `ApiClient::with_decoder`; they are tried
+/// before the built-in JSON, form and text decoders.
+pub trait ResponseDecoder {
+    /// Whether this decoder handles the media type, e.g. `application/json`
+    fn handles(&self, media_type: &str) -> bool;
+
+    /// Decode the body of the response
+    fn decode(&self, response: &ApiResponse) -> Result<Value, AppError>;
+}
+
+/// Decodes `application/json` and `+json` media types
+pub struct JsonDecoder;
+
+impl ResponseDecoder for JsonDecoder {
+    fn handles(&self, media_type: &str) -> bool {
+        media_type == "application/json"
+            || media_type == "text/json"
+            || media_type.ends_with("+json")
+    }
+
+    fn decode(&self, response: &ApiResponse) -> Result<Value, AppError> {
+        parse_json(&response.body)
+    }
+}
+
+/// Decodes `application/x-www-form-urlencoded` into an object of strings
+///
+/// Repeated keys are collected into an array.
+pub struct FormDecoder;
+
+impl ResponseDecoder for FormDecoder {
+    fn handles(&self, media_type: &str) -> bool {
+        media_type == "application/x-www-form-urlencoded"
+    }
+
+    fn decode(&self, response: &ApiResponse) -> Result<Value, AppError> {
+        let mut object = Map::new();
+
+        for pair in response
+            .body
+            .trim()
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+        {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let key = decode_form_component(key)?;
+            let value = Value::String(decode_form_component(value)?);
+
+            match object.get_mut(&key) {
+                Some(Value::Array(values)) => values.push(value),
+                Some(existing) => *existing = Value::Array(vec![existing.take(), value]),
+                None => {
+                    object.insert(key, value);
+                }
+            }
+        }
+
+        Ok(Value::Object(object))
+    }
+}
+
+/// Decodes `text/*` media types into a JSON string
+pub struct TextDecoder;
+
+impl ResponseDecoder for TextDecoder {
+    fn handles(&self, media_type: &str) -> bool {
+        media_type.starts_with("text/")
+    }
+
+    fn decode(&self, response: &ApiResponse) -> Result<Value, AppError> {
+        Ok(Value::String(response.body.clone()))
+    }
+}
+
+/// The decoders every ApiClient starts with
+pub fn default_decoders() -> Vec<Arc<dyn ResponseDecoder>> {
+    vec![
+        Arc::new(JsonDecoder),
+        Arc::new(FormDecoder),
+        Arc::new(TextDecoder),
+    ]
+}
+
+/// Decode a response with the first decoder that handles its `Content-Type`
+///
+/// Empty bodies, e.g. from a 204 No Content, become an empty JSON object.
+/// Responses without a known content type are parsed as JSON.
+pub fn decode(
+    response: &ApiResponse,
+    decoders: &[Arc<dyn ResponseDecoder>],
+) -> Result<Value, AppError> {
+    let has_bytes = response
+        .body_bytes
+        .as_ref()
+        .is_some_and(|bytes| !bytes.is_empty());
+    if response.body.trim().is_empty() && !has_bytes {
+        return Ok(Value::Object(Map::new()));
+    }
+
+    let media_type = response
+        .header("Content-Type")
+        .and_then(|content_type| content_type.split(';').next())
+        .map(|media_type| media_type.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    match decoders.iter().find(|decoder| decoder.handles(&media_type)) {
+        Some(decoder) => decoder.decode(response),
+        None => parse_json(&response.body).map_err(|_e| AppError {
+            code: ErrorCode::MalformedResponse,
+            message: format!(
+                "Invalid API response format - unsupported content type '{}'",
+                media_type
+            ),
+        }),
+    }
+}
+
+/// Parse a JSON body
+fn parse_json(body: &str) -> Result<Value, AppError> {
+    serde_json::from_str(body).map_err(|_e| AppError {
+        code: ErrorCode::MalformedResponse,
+        message: "Invalid API response format".to_string(),
+    })
+}
+
+/// Encode fields as `application/x-www-form-urlencoded`
+pub fn encode_form(fields: &[(String, String)]) -> String {
+    fields
+        .iter()
+        .map(|(key, value)| {
+            format!(
+                "{}={}",
+                urlencoding::encode(key),
+                urlencoding::encode(value)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Decode a key or value of a form-urlencoded body
+fn decode_form_component(component: &str) -> Result<String, AppError> {
+    urlencoding::decode(&component.replace('+', " "))
+        .map(|decoded| decoded.into_owned())
+        .map_err(|_e| AppError {
+            code: ErrorCode::MalformedResponse,
+            message: "Invalid form-urlencoded response body".to_string(),
+        })
+}
@@ -0,0 +1,454 @@
+use super::pagination::{Page, PageSource, Pages};
+use super::{ApiClient, typed};
+use crate::standout::app::{
+    http::Method,
+    types::{AppError, ErrorCode},
+};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{Map, Value, json};
+
+/// A GraphQL operation being built against an ApiClient
+///
+/// Created with `ApiClient::graphql`. The document, variables and operation
+/// name are POSTed as JSON. Queries are retried like other idempotent
+/// requests; documents containing a mutation are not.
+///
+/// ```ignore
+/// let viewer = client
+///     .graphql("/graphql", "query Viewer($first: Int!) { viewer { login } }")
+///     .variable("first", 10)
+///     .send()?;
+/// ```
+#[derive(Clone)]
+pub struct GraphQlRequest<'a> {
+    client: &'a ApiClient,
+    endpoint: String,
+    document: String,
+    variables: Map<String, Value>,
+    operation_name: Option<String>,
+}
+
+impl<'a> GraphQlRequest<'a> {
+    pub(super) fn new(client: &'a ApiClient, endpoint: &str, document: &str) -> Self {
+        GraphQlRequest {
+            client,
+            endpoint: endpoint.to_string(),
+            document: document.to_string(),
+            variables: Map::new(),
+            operation_name: None,
+        }
+    }
+
+    /// Set one variable
+    #[allow(dead_code)]
+    pub fn variable(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.variables.insert(name.into(), value.into());
+        self
+    }
+
+    /// Set a variable from any `Serialize` value
+    #[allow(dead_code)]
+    pub fn typed_variable<V: Serialize + ?Sized>(
+        self,
+        name: impl Into<String>,
+        value: &V,
+    ) -> Result<Self, AppError> {
+        Ok(self.variable(name, typed::to_value(value)?))
+    }
+
+    /// Set every field of a JSON object as a variable
+    #[allow(dead_code)]
+    pub fn variables(mut self, variables: Value) -> Self {
+        if let Value::Object(variables) = variables {
+            self.variables.extend(variables);
+        }
+        self
+    }
+
+    /// Select the operation to run when the document holds several
+    #[allow(dead_code)]
+    pub fn operation_name(mut self, operation_name: impl Into<String>) -> Self {
+        self.operation_name = Some(operation_name.into());
+        self
+    }
+
+    /// Run the operation and return its `data`
+    ///
+    /// Entries in `errors` become an `AppError`, with the code taken from
+    /// `extensions.code` (see `error_code_for_graphql`).
+    pub fn send(&self) -> Result<Value, AppError> {
+        let mut payload = json!({
+            "query": self.document,
+            "variables": self.variables,
+        });
+        if let Some(operation_name) = &self.operation_name {
+            payload["operationName"] = json!(operation_name);
+        }
+
+        let mut request = self
+            .client
+            .request(Method::Post, &self.endpoint)
+            .header("Accept", "application/json")
+            .body(&payload);
+        if !is_mutation(&self.document) {
+            request = request.idempotent();
+        }
+        let url = request.url();
+
+        let mut response = request.send()?;
+
+        if let Some(errors) = response.get("errors").and_then(|v| v.as_array())
+            && !errors.is_empty()
+        {
+            return Err(graphql_error(&url, errors));
+        }
+
+        match response.get_mut("data").map(Value::take) {
+            Some(data) if !data.is_null() => Ok(data),
+            _ => Err(AppError {
+                code: ErrorCode::MalformedResponse,
+                message: format!("GraphQL response has no data - URL: {}", url),
+            }),
+        }
+    }
+
+    /// Run the operation and deserialize its `data` into `T`
+    #[allow(dead_code)]
+    pub fn send_as<T: DeserializeOwned>(&self) -> Result<T, AppError> {
+        typed::from_value(self.send()?)
+    }
+
+    /// Page through the Relay connection at `connection_path` in `data`
+    ///
+    /// `connection_path` is a JSON pointer such as `/repository/issues`. The
+    /// document must select `pageInfo { hasNextPage endCursor }` on the
+    /// connection and take the cursor as the `$after` variable.
+    #[allow(dead_code)]
+    pub fn paginate(self, connection_path: impl Into<String>) -> RelayPaginator<'a> {
+        RelayPaginator {
+            pages: Pages::new(RelayPages {
+                request: self,
+                connection_path: connection_path.into(),
+                cursor_variable: "after".to_string(),
+            }),
+        }
+    }
+}
+
+/// Lazily yields the nodes of a Relay connection, one page at a time
+///
+/// Created with `GraphQlRequest::paginate`. Nodes are read from `nodes`, or
+/// from `edges[].node` when the connection only selects edges.
+///
+/// ```ignore
+/// let mut issues = client
+///     .graphql("/graphql", ISSUES_QUERY)
+///     .variable("owner", "octocat")
+///     .paginate("/repository/issues");
+///
+/// if let Some(cursor) = store.cursor.take() {
+///     issues = issues.resume_from(cursor);
+/// }
+///
+/// let nodes = issues.by_ref().collect::<Result<Vec<_>, _>>()?;
+/// store.cursor = issues.continuation();
+/// ```
+pub struct RelayPaginator<'a> {
+    pages: Pages<RelayPages<'a>>,
+}
+
+impl RelayPaginator<'_> {
+    /// Name of the variable the cursor is sent as (defaults to `after`)
+    #[allow(dead_code)]
+    pub fn cursor_variable(mut self, name: impl Into<String>) -> Self {
+        self.pages.source.cursor_variable = name.into();
+        self
+    }
+
+    /// Stop after yielding this many nodes
+    #[allow(dead_code)]
+    pub fn max_items(mut self, max_items: usize) -> Self {
+        self.pages.max_items = Some(max_items);
+        self
+    }
+
+    /// Start from a cursor previously returned by `continuation`
+    #[allow(dead_code)]
+    pub fn resume_from(mut self, cursor: impl Into<String>) -> Self {
+        self.pages.page_token = Some(cursor.into());
+        self
+    }
+
+    /// Cursor to pass to `resume_from` to continue after the nodes yielded so far
+    ///
+    /// When iteration stops in the middle of a page, the cursor points at
+    /// that page, so some nodes may be yielded again when resuming. Returns
+    /// `None` when all pages have been read or the first page was not
+    /// finished.
+    #[allow(dead_code)]
+    pub fn continuation(&self) -> Option<String> {
+        self.pages.continuation()
+    }
+
+    /// Whether every page has been requested and every node yielded
+    #[allow(dead_code)]
+    pub fn is_exhausted(&self) -> bool {
+        self.pages.is_exhausted()
+    }
+}
+
+impl Iterator for RelayPaginator<'_> {
+    type Item = Result<Value, AppError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.pages.next()
+    }
+}
+
+/// The pages of a Relay connection, requested with the cursor as a variable
+struct RelayPages<'a> {
+    request: GraphQlRequest<'a>,
+    connection_path: String,
+    cursor_variable: String,
+}
+
+impl PageSource for RelayPages<'_> {
+    fn fetch_page(&self, cursor: Option<&str>) -> Result<Page, AppError> {
+        let request = self
+            .request
+            .clone()
+            .variable(self.cursor_variable.clone(), cursor);
+
+        let data = request.send()?;
+        let connection = data
+            .pointer(&self.connection_path)
+            .ok_or_else(|| AppError {
+                code: ErrorCode::MalformedResponse,
+                message: format!(
+                    "No connection at '{}' in GraphQL data",
+                    self.connection_path
+                ),
+            })?;
+
+        let nodes: Vec<Value> = match (connection.get("nodes"), connection.get("edges")) {
+            (Some(Value::Array(nodes)), _) => nodes.clone(),
+            (_, Some(Value::Array(edges))) => edges
+                .iter()
+                .filter_map(|edge| edge.get("node").cloned())
+                .collect(),
+            _ => {
+                return Err(AppError {
+                    code: ErrorCode::MalformedResponse,
+                    message: format!(
+                        "No nodes or edges at '{}' in GraphQL data",
+                        self.connection_path
+                    ),
+                });
+            }
+        };
+
+        let page_info = connection.get("pageInfo").ok_or_else(|| AppError {
+            code: ErrorCode::MalformedResponse,
+            message: format!("No pageInfo at '{}' in GraphQL data", self.connection_path),
+        })?;
+        let has_next_page = page_info
+            .get("hasNextPage")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+
+        let next_token = match page_info.get("endCursor") {
+            Some(Value::String(cursor)) if has_next_page => Some(cursor.clone()),
+            _ => None,
+        };
+
+        Ok(Page {
+            items: nodes,
+            next_token,
+        })
+    }
+}
+
+/// Map a GraphQL error code to an ErrorCode
+///
+/// Understands the Apollo codes in `extensions.code` and the common variants
+/// used by GitHub, Shopify and others (compared case-insensitively).
+pub fn error_code_for_graphql(code: &str) -> ErrorCode {
+    match code.to_ascii_uppercase().replace('-', "_").as_str() {
+        "UNAUTHENTICATED" | "UNAUTHORIZED" | "INVALID_TOKEN" => ErrorCode::Unauthenticated,
+        "FORBIDDEN" | "ACCESS_DENIED" | "PERMISSION_DENIED" => ErrorCode::Forbidden,
+        "RATE_LIMITED" | "RATE_LIMIT" | "THROTTLED" | "MAX_COST_EXCEEDED" => ErrorCode::RateLimit,
+        "GRAPHQL_PARSE_FAILED" | "GRAPHQL_VALIDATION_FAILED" | "PERSISTED_QUERY_NOT_FOUND" => {
+            ErrorCode::Misconfigured
+        }
+        "OPERATION_RESOLUTION_FAILURE" | "PERSISTED_QUERY_NOT_SUPPORTED" => ErrorCode::Unsupported,
+        "TIMEOUT" | "TIMED_OUT" => ErrorCode::Timeout,
+        "SERVICE_UNAVAILABLE" | "INTERNAL_SERVER_ERROR" | "INTERNAL" => ErrorCode::Unavailable,
+        _ => ErrorCode::Other,
+    }
+}
+
+/// Turn the `errors` array of a response into an AppError
+///
+/// The first error with a recognized code decides the ErrorCode; the message
+/// lists every error with its path.
+fn graphql_error(url: &str, errors: &[Value]) -> AppError {
+    let code = errors
+        .iter()
+        .filter_map(|error| {
+            error
+                .pointer("/extensions/code")
+                .or_else(|| error.get("type"))
+                .and_then(|v| v.as_str())
+        })
+        .map(error_code_for_graphql)
+        .find(|code| !matches!(code, ErrorCode::Other))
+        .unwrap_or(ErrorCode::Other);
+
+    let messages = errors
+        .iter()
+        .map(|error| {
+            let message = error
+                .get("message")
+                .and_then(|v| v.as_str())
+                .unwrap_or("Unknown error");
+            match error.get("path").and_then(|v| v.as_array()) {
+                Some(path) if !path.is_empty() => {
+                    let path = path
+                        .iter()
+                        .map(|segment| match segment {
+                            Value::String(s) => s.clone(),
+                            other => other.to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(".");
+                    format!("{} (at {})", message, path)
+                }
+                _ => message.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("; ");
+
+    AppError {
+        code,
+        message: format!(
+            "GraphQL request failed - URL: {} - Errors: {}",
+            url, messages
+        ),
+    }
+}
+
+/// Whether the document contains a mutation, which must not be retried
+fn is_mutation(document: &str) -> bool {
+    document
+        .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .any(|word| word == "mutation")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::{self, mock, received_requests};
+
+    #[test]
+    fn error_codes_are_mapped_case_insensitively() {
+        let cases = [
+            ("UNAUTHENTICATED", ErrorCode::Unauthenticated),
+            ("invalid-token", ErrorCode::Unauthenticated),
+            ("FORBIDDEN", ErrorCode::Forbidden),
+            ("MAX_COST_EXCEEDED", ErrorCode::RateLimit),
+            ("Throttled", ErrorCode::RateLimit),
+            ("GRAPHQL_VALIDATION_FAILED", ErrorCode::Misconfigured),
+            ("PERSISTED_QUERY_NOT_SUPPORTED", ErrorCode::Unsupported),
+            ("timeout", ErrorCode::Timeout),
+            ("INTERNAL_SERVER_ERROR", ErrorCode::Unavailable),
+            ("NOT_FOUND", ErrorCode::Other),
+        ];
+        for (code, expected) in cases {
+            assert_eq!(
+                std::mem::discriminant(&error_code_for_graphql(code)),
+                std::mem::discriminant(&expected),
+                "{}",
+                code
+            );
+        }
+    }
+
+    #[test]
+    fn the_first_recognized_code_decides_and_every_message_is_listed() {
+        let errors = [
+            json!({ "message": "Not found", "path": ["repository", "issues", 0], "extensions": { "code": "NOT_FOUND" } }),
+            json!({ "message": "Slow down", "type": "RATE_LIMITED" }),
+            json!({ "extensions": { "code": "FORBIDDEN" } }),
+        ];
+        let err = graphql_error("https://api.example.com/graphql", &errors);
+
+        assert!(matches!(err.code, ErrorCode::RateLimit));
+        assert_eq!(
+            err.message,
+            "GraphQL request failed - URL: https://api.example.com/graphql - Errors: \
+             Not found (at repository.issues.0); Slow down; Unknown error"
+        );
+
+        let err = graphql_error("/graphql", &[json!({ "message": "Oops" })]);
+        assert!(matches!(err.code, ErrorCode::Other));
+    }
+
+    #[test]
+    fn mutations_are_found_as_whole_words() {
+        assert!(is_mutation("mutation { addStar(id: 1) { id } }"));
+        assert!(is_mutation(
+            "# comment\nmutation AddStar($id: ID!) { addStar(id: $id) { id } }"
+        ));
+        assert!(!is_mutation("query { mutations { id } }"));
+        assert!(!is_mutation("{ viewer { mutation_count } }"));
+    }
+
+    #[test]
+    fn relay_connections_are_paged_through_with_the_end_cursor() {
+        harness::reset();
+        mock(Method::Post, "/graphql").respond_json(
+            200,
+            &json!({ "data": { "repository": { "issues": {
+                "edges": [{ "node": { "id": 3 } }],
+                "pageInfo": { "hasNextPage": false, "endCursor": "c3" }
+            } } } }),
+        );
+        mock(Method::Post, "/graphql").times(1).respond_json(
+            200,
+            &json!({ "data": { "repository": { "issues": {
+                "nodes": [{ "id": 1 }, { "id": 2 }],
+                "pageInfo": { "hasNextPage": true, "endCursor": "c2" }
+            } } } }),
+        );
+
+        let client = ApiClient::new(&json!({
+            "base_url": "https://api.example.com",
+            "headers": {},
+        }))
+        .unwrap();
+        let mut issues = client
+            .graphql("/graphql", "query Issues($after: String) { ... }")
+            .variable("owner", "octocat")
+            .paginate("/repository/issues");
+        let nodes = issues.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+
+        assert_eq!(
+            nodes,
+            [json!({ "id": 1 }), json!({ "id": 2 }), json!({ "id": 3 })]
+        );
+        assert!(issues.is_exhausted());
+        assert_eq!(issues.continuation(), None);
+
+        let received = received_requests();
+        assert_eq!(received.len(), 2);
+        assert_eq!(
+            received[0].json()["variables"],
+            json!({ "owner": "octocat", "after": null })
+        );
+        assert_eq!(
+            received[1].json()["variables"],
+            json!({ "owner": "octocat", "after": "c2" })
+        );
+    }
+}
@@ -0,0 +1,663 @@
+pub mod auth;
+mod body;
+mod graphql;
+mod multipart;
+mod oauth2;
+mod pagination;
+mod profile;
+mod rate_limit;
+mod response;
+mod retry;
+mod trace;
+mod typed;
+pub mod url;
+
+pub use auth::{AuthStrategy, AuthTarget};
+pub use body::{RequestBody, ResponseDecoder};
+pub use graphql::GraphQlRequest;
+pub use multipart::Multipart;
+pub use pagination::{Pagination, Paginator};
+pub use profile::ConnectionProfile;
+pub use rate_limit::RateLimiter;
+pub use response::{ApiResponse, Headers};
+pub use retry::RetryPolicy;
+pub use trace::Tracer;
+// Cassettes scrub their recordings with the trace deny-list
+#[cfg(any(test, feature = "test-harness"))]
+pub use trace::Redactor;
+
+use crate::deadline;
+use crate::standout::app::{
+    http::{Method, RequestBuilder, RequestError, Response},
+    types::{AppError, ErrorCode},
+};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// HTTP client for making API requests
+#[derive(Clone)]
+pub struct ApiClient {
+    base_url: String,
+    headers: HashMap<String, String>,
+    retry_policy: RetryPolicy,
+    decoders: Vec<Arc<dyn ResponseDecoder>>,
+    auth: Option<Arc<dyn AuthStrategy>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    tracer: Tracer,
+}
+
+impl ApiClient {
+    /// Create a new ApiClient from connection data
+    ///
+    /// The connection data is read as described by the connection profile
+    /// (see `ConnectionProfile`). When it selects an auth strategy (see
+    /// `auth::from_connection`), the credentials are added to every request
+    /// and `headers` is optional.
+    #[allow(dead_code)]
+    pub fn new(connection_data: &Value) -> Result<Self, AppError> {
+        Self::with_profile(&ConnectionProfile::embedded()?, connection_data)
+    }
+
+    /// Create a new ApiClient from connection data laid out as the profile describes
+    pub fn with_profile(
+        profile: &ConnectionProfile,
+        connection_data: &Value,
+    ) -> Result<Self, AppError> {
+        let base_url = profile.base_url(connection_data)?;
+
+        // An auth scheme declared by the connector wins over the connection's own
+        let auth = match profile.auth(connection_data)? {
+            Some(auth) => Some(auth::from_value(&auth, connection_data)?),
+            None => auth::from_connection(connection_data)?,
+        };
+
+        let headers = profile
+            .headers(connection_data, auth.is_some())?
+            .into_iter()
+            .collect();
+        let rate_limiter = profile.rate_limiter(connection_data)?.map(Arc::new);
+
+        Ok(ApiClient {
+            base_url,
+            headers,
+            retry_policy: RetryPolicy::default(),
+            decoders: body::default_decoders(),
+            auth,
+            rate_limiter,
+            tracer: Tracer::from_env(),
+        })
+    }
+
+    /// Replace the auth strategy configured in the connection data
+    #[allow(dead_code)]
+    pub fn with_auth(mut self, auth: impl AuthStrategy + 'static) -> Self {
+        self.auth = Some(Arc::new(auth));
+        self
+    }
+
+    /// A copy of this client without default headers, auth or rate limit, e.g. for token endpoints
+    fn without_auth(&self) -> ApiClient {
+        ApiClient {
+            base_url: String::new(),
+            headers: HashMap::new(),
+            auth: None,
+            rate_limiter: None,
+            ..self.clone()
+        }
+    }
+
+    /// Space out requests with a token bucket shared by clones of this client
+    #[allow(dead_code)]
+    pub fn with_rate_limit(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(Arc::new(rate_limiter));
+        self
+    }
+
+    /// Replace the retry policy used for every request made by this client
+    #[allow(dead_code)]
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Redact these headers, query parameters and JSON fields in traces
+    ///
+    /// Names are added to the deny-list read from `STANDOUT_HTTP_TRACE_REDACT`.
+    #[allow(dead_code)]
+    pub fn with_trace_redaction<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.tracer = self.tracer.redact(names);
+        self
+    }
+
+    /// Add a response decoder, tried before the decoders already registered
+    #[allow(dead_code)]
+    pub fn with_decoder(mut self, decoder: impl ResponseDecoder + 'static) -> Self {
+        self.decoders.insert(0, Arc::new(decoder));
+        self
+    }
+
+    /// Decode a response body into JSON based on its `Content-Type`
+    pub fn decode(&self, response: &ApiResponse) -> Result<Value, AppError> {
+        body::decode(response, &self.decoders)
+    }
+
+    /// Start building a request against an endpoint relative to the base URL
+    #[allow(dead_code)]
+    pub fn request(&self, method: Method, endpoint: &str) -> ApiRequest<'_> {
+        ApiRequest {
+            client: self,
+            method,
+            endpoint: endpoint.to_string(),
+            query: Vec::new(),
+            headers: Vec::new(),
+            body: None,
+            idempotent: retry::is_idempotent(&method),
+        }
+    }
+
+    /// Iterate over the items of a paginated GET endpoint
+    #[allow(dead_code)]
+    pub fn paginate(&self, endpoint: &str, pagination: Pagination) -> Paginator<'_> {
+        Paginator::new(self, endpoint, pagination)
+    }
+
+    /// Start a GraphQL operation against the endpoint, e.g. `/graphql`
+    #[allow(dead_code)]
+    pub fn graphql(&self, endpoint: &str, document: &str) -> GraphQlRequest<'_> {
+        GraphQlRequest::new(self, endpoint, document)
+    }
+
+    /// Make a GET request and return the response body
+    #[allow(dead_code)]
+    pub fn get(&self, endpoint: &str) -> Result<Value, AppError> {
+        self.request(Method::Get, endpoint).send()
+    }
+
+    /// Make a POST request with JSON body and return the response body
+    #[allow(dead_code)]
+    pub fn post(&self, endpoint: &str, body: &Value) -> Result<Value, AppError> {
+        self.request(Method::Post, endpoint).body(body).send()
+    }
+
+    /// Make a PATCH request with JSON body and return the response body
+    #[allow(dead_code)]
+    pub fn patch(&self, endpoint: &str, body: &Value) -> Result<Value, AppError> {
+        self.request(Method::Patch, endpoint).body(body).send()
+    }
+
+    /// Make a PUT request with JSON body and return the response body
+    #[allow(dead_code)]
+    pub fn put(&self, endpoint: &str, body: &Value) -> Result<Value, AppError> {
+        self.request(Method::Put, endpoint).body(body).send()
+    }
+
+    /// Make a DELETE request and return the response body
+    #[allow(dead_code)]
+    pub fn delete(&self, endpoint: &str) -> Result<Value, AppError> {
+        self.request(Method::Delete, endpoint).send()
+    }
+
+    /// Make a GET request and deserialize the response body into `T`
+    #[allow(dead_code)]
+    pub fn get_as<T: DeserializeOwned>(&self, endpoint: &str) -> Result<T, AppError> {
+        self.request(Method::Get, endpoint).send_as()
+    }
+
+    /// Make a POST request with a serialized body and deserialize the response into `T`
+    #[allow(dead_code)]
+    pub fn post_as<B: Serialize + ?Sized, T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        body: &B,
+    ) -> Result<T, AppError> {
+        self.request(Method::Post, endpoint)
+            .body(&typed::to_value(body)?)
+            .send_as()
+    }
+
+    /// Make a PATCH request with a serialized body and deserialize the response into `T`
+    #[allow(dead_code)]
+    pub fn patch_as<B: Serialize + ?Sized, T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        body: &B,
+    ) -> Result<T, AppError> {
+        self.request(Method::Patch, endpoint)
+            .body(&typed::to_value(body)?)
+            .send_as()
+    }
+
+    /// Make a PUT request with a serialized body and deserialize the response into `T`
+    #[allow(dead_code)]
+    pub fn put_as<B: Serialize + ?Sized, T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        body: &B,
+    ) -> Result<T, AppError> {
+        self.request(Method::Put, endpoint)
+            .body(&typed::to_value(body)?)
+            .send_as()
+    }
+
+    /// Make a DELETE request and deserialize the response body into `T`
+    #[allow(dead_code)]
+    pub fn delete_as<T: DeserializeOwned>(&self, endpoint: &str) -> Result<T, AppError> {
+        self.request(Method::Delete, endpoint).send_as()
+    }
+
+    /// Make a GET request and return the full response
+    #[allow(dead_code)]
+    pub fn get_response(&self, endpoint: &str) -> Result<ApiResponse, AppError> {
+        self.request(Method::Get, endpoint).response()
+    }
+
+    /// Make a POST request with JSON body and return the full response
+    #[allow(dead_code)]
+    pub fn post_response(&self, endpoint: &str, body: &Value) -> Result<ApiResponse, AppError> {
+        self.request(Method::Post, endpoint).body(body).response()
+    }
+
+    /// Make a PATCH request with JSON body and return the full response
+    #[allow(dead_code)]
+    pub fn patch_response(&self, endpoint: &str, body: &Value) -> Result<ApiResponse, AppError> {
+        self.request(Method::Patch, endpoint).body(body).response()
+    }
+
+    /// Make a PUT request with JSON body and return the full response
+    #[allow(dead_code)]
+    pub fn put_response(&self, endpoint: &str, body: &Value) -> Result<ApiResponse, AppError> {
+        self.request(Method::Put, endpoint).body(body).response()
+    }
+
+    /// Make a DELETE request and return the full response
+    #[allow(dead_code)]
+    pub fn delete_response(&self, endpoint: &str) -> Result<ApiResponse, AppError> {
+        self.request(Method::Delete, endpoint).response()
+    }
+}
+
+/// A request being built against an ApiClient
+///
+/// Created with `ApiClient::request`. Per-request headers override the
+/// client's default headers with the same name (compared case-insensitively).
+/// Transient failures are retried according to the client's `RetryPolicy`,
+/// but only for idempotent methods unless the request is marked `idempotent`.
+///
+/// ```ignore
+/// let issues = client
+///     .request(Method::Get, "/issues")
+///     .query("state", "open")
+///     .header("Accept", "application/vnd.github+json")
+///     .send()?;
+/// ```
+pub struct ApiRequest<'a> {
+    client: &'a ApiClient,
+    method: Method,
+    endpoint: String,
+    query: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+    body: Option<RequestBody>,
+    idempotent: bool,
+}
+
+impl ApiRequest<'_> {
+    /// Add a query parameter, percent-encoded when the URL is built
+    #[allow(dead_code)]
+    pub fn query(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.query.push((key.into(), value.into()));
+        self
+    }
+
+    /// Add several query parameters, e.g. from a `HashMap<String, String>`
+    #[allow(dead_code)]
+    pub fn query_map<I, K, V>(mut self, params: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.query
+            .extend(params.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Add a header for this request only
+    #[allow(dead_code)]
+    pub fn header(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((key.into(), value.into()));
+        self
+    }
+
+    /// Fill the endpoint's URI template, e.g. `/users/{id}/posts{?since}`
+    ///
+    /// Values are percent-encoded, so an ID containing `/` or `?` stays in
+    /// its path segment (see `url::expand`).
+    #[allow(dead_code)]
+    pub fn path_params(mut self, values: &Value) -> Result<Self, AppError> {
+        self.endpoint = url::expand(&self.endpoint, values)?;
+        Ok(self)
+    }
+
+    /// Set a JSON body for the request
+    #[allow(dead_code)]
+    pub fn body(self, body: &Value) -> Self {
+        self.encoded_body(RequestBody::Json(body.clone()))
+    }
+
+    /// Set a JSON body serialized from any `Serialize` value
+    #[allow(dead_code)]
+    pub fn json<B: Serialize + ?Sized>(self, body: &B) -> Result<Self, AppError> {
+        Ok(self.encoded_body(RequestBody::Json(typed::to_value(body)?)))
+    }
+
+    /// Set a `multipart/form-data` body, sent through `body-bytes`
+    #[allow(dead_code)]
+    pub fn multipart(self, form: Multipart) -> Self {
+        self.encoded_body(RequestBody::Multipart(form))
+    }
+
+    /// Set an `application/x-www-form-urlencoded` body
+    #[allow(dead_code)]
+    pub fn form<I, K, V>(self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let fields = fields
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect();
+        self.encoded_body(RequestBody::Form(fields))
+    }
+
+    /// Set a `text/plain` body
+    #[allow(dead_code)]
+    pub fn text(self, content: impl Into<String>) -> Self {
+        self.encoded_body(RequestBody::Text {
+            content: content.into(),
+            content_type: "text/plain".to_string(),
+        })
+    }
+
+    /// Set a binary body, sent through `body-bytes`
+    #[allow(dead_code)]
+    pub fn bytes(self, data: Vec<u8>, content_type: impl Into<String>) -> Self {
+        self.encoded_body(RequestBody::Bytes {
+            data,
+            content_type: content_type.into(),
+        })
+    }
+
+    /// Set the body with an explicit encoding
+    ///
+    /// The body's content type replaces a `Content-Type` from the connection's
+    /// default headers, but not one set with `header` on this request.
+    #[allow(dead_code)]
+    pub fn encoded_body(mut self, body: RequestBody) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    /// Allow retries for a non-idempotent request, e.g. a POST with an idempotency key
+    #[allow(dead_code)]
+    pub fn idempotent(mut self) -> Self {
+        self.idempotent = true;
+        self
+    }
+
+    /// The full URL including the encoded query string
+    pub fn url(&self) -> String {
+        // Absolute URLs, e.g. from a `Link` header, are used as they are
+        append_query(
+            url::join(&self.client.base_url, &self.endpoint),
+            &self.query,
+        )
+    }
+
+    /// The headers and extra query parameters to send
+    ///
+    /// Starts from the client's default headers, adds the credentials of the
+    /// auth strategy, then the per-request headers and the body content type.
+    /// Also returns the names of the headers the auth strategy set.
+    fn prepare(&self) -> Result<(AuthTarget, Vec<String>), AppError> {
+        let mut target = AuthTarget {
+            headers: self
+                .client
+                .headers
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            query: Vec::new(),
+        };
+
+        let mut credential_headers = Vec::new();
+        if let Some(auth) = &self.client.auth {
+            let before = target.headers.clone();
+            auth.apply(self.client, &mut target)?;
+            credential_headers = target
+                .headers
+                .iter()
+                .filter(|header| !before.contains(header))
+                .map(|(key, _)| key.clone())
+                .collect();
+        }
+
+        target.headers.retain(|(key, _)| {
+            !self
+                .headers
+                .iter()
+                .any(|(override_key, _)| override_key.eq_ignore_ascii_case(key))
+        });
+        target.headers.extend(self.headers.iter().cloned());
+
+        let has_content_type = self
+            .headers
+            .iter()
+            .any(|(key, _)| key.eq_ignore_ascii_case("Content-Type"));
+        if let Some(body) = &self.body
+            && !has_content_type
+        {
+            target.set_header("Content-Type", body.content_type());
+        }
+
+        Ok((target, credential_headers))
+    }
+
+    /// Send the request and return the parsed JSON response body
+    pub fn send(self) -> Result<Value, AppError> {
+        let response = self.send_with_retries()?;
+        self.client.decode(&response)
+    }
+
+    /// Send the request and deserialize the response body into `T`
+    ///
+    /// A body that does not match `T` is a `MalformedResponse` error naming
+    /// the JSON path that failed.
+    #[allow(dead_code)]
+    pub fn send_as<T: DeserializeOwned>(self) -> Result<T, AppError> {
+        typed::from_value(self.send()?)
+    }
+
+    /// Send the request and return the full response, including status and headers
+    #[allow(dead_code)]
+    pub fn response(self) -> Result<ApiResponse, AppError> {
+        self.send_with_retries()
+    }
+
+    /// Send the request, retrying transient failures, and return a 2xx response
+    fn send_with_retries(&self) -> Result<ApiResponse, AppError> {
+        let url = self.url();
+        let policy = &self.client.retry_policy;
+        let started = Instant::now();
+        let mut attempt = 1;
+        let mut refreshed_credentials = false;
+
+        loop {
+            let (error, retryable, requested_delay) = match self.send_once(&url)? {
+                Ok(response) if (200..300).contains(&response.status) => {
+                    return Ok(response.into());
+                }
+                // Expired or revoked credentials get one refresh per request
+                Ok(response) if response.status == 401 && !refreshed_credentials => {
+                    refreshed_credentials = true;
+                    if let Some(auth) = &self.client.auth
+                        && auth.refresh(self.client)?
+                    {
+                        continue;
+                    }
+                    (
+                        status_error(&url, response.status, &response.body),
+                        false,
+                        None,
+                    )
+                }
+                Ok(response) => (
+                    status_error(&url, response.status, &response.body),
+                    policy.retries_status(response.status),
+                    retry::requested_delay(&response.headers.into()),
+                ),
+                Err(err) => (request_error(&url, err), true, None),
+            };
+
+            if !retryable || !self.idempotent || attempt >= policy.max_attempts {
+                return Err(error);
+            }
+
+            let delay = requested_delay.unwrap_or_else(|| policy.backoff(attempt));
+            // A server can ask for any wait, e.g. `Retry-After: 18446744073709551615`
+            let finished = started.elapsed().checked_add(delay);
+            if finished.is_none_or(|finished| finished > policy.max_elapsed)
+                || !deadline::allows(delay)
+            {
+                return Err(error);
+            }
+
+            std::thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+
+    /// Send a single attempt of the request
+    ///
+    /// Fails with `ErrorCode::Timeout` when the invocation budget is nearly
+    /// spent, instead of starting a request the host would cut off.
+    ///
+    /// `url` is the request URL without credentials; query parameters added
+    /// by the auth strategy are appended here so they never end up in errors.
+    fn send_once(&self, url: &str) -> Result<Result<Response, RequestError>, AppError> {
+        deadline::check().map_err(|e| AppError {
+            code: e.code,
+            message: format!("{} - not sending {}", e.message, url),
+        })?;
+
+        if let Some(rate_limiter) = &self.client.rate_limiter {
+            rate_limiter.acquire()?;
+        }
+
+        let (target, credential_headers) = self.prepare()?;
+        let full_url = append_query(url.to_string(), &target.query);
+
+        let mut request_builder = RequestBuilder::new().method(self.method).url(&full_url);
+
+        for (key, value) in &target.headers {
+            request_builder = request_builder.header(key, value);
+        }
+
+        if let Some(body) = &self.body {
+            request_builder = body.apply(request_builder)?;
+        }
+
+        if !self.client.tracer.is_enabled() {
+            return Ok(request_builder.send());
+        }
+
+        let started = Instant::now();
+        let outcome = request_builder.send();
+        self.client.tracer.record(
+            self.method,
+            url,
+            &target.headers,
+            &credential_headers,
+            self.body.as_ref(),
+            &outcome,
+            started.elapsed(),
+        );
+
+        Ok(outcome)
+    }
+}
+
+/// Append percent-encoded query parameters to a URL
+fn append_query(mut url: String, query: &[(String, String)]) -> String {
+    for (index, (key, value)) in query.iter().enumerate() {
+        let separator = if index == 0 && !url.contains('?') {
+            '?'
+        } else {
+            '&'
+        };
+        url.push(separator);
+        url.push_str(&urlencoding::encode(key));
+        url.push('=');
+        url.push_str(&urlencoding::encode(value));
+    }
+
+    url
+}
+
+/// Map an HTTP status code to the error code the platform should see
+pub fn error_code_for_status(status: u16) -> ErrorCode {
+    match status {
+        401 => ErrorCode::Unauthenticated,
+        403 => ErrorCode::Forbidden,
+        429 => ErrorCode::RateLimit,
+        408 | 504 => ErrorCode::Timeout,
+        405 | 501 => ErrorCode::Unsupported,
+        500..=599 => ErrorCode::Unavailable,
+        _ => ErrorCode::Other,
+    }
+}
+
+/// Map the message of a failed `send()` to the error code the platform should see
+pub fn error_code_for_request_error(message: &str) -> ErrorCode {
+    let message = message.to_lowercase();
+
+    if message.contains("timed out") || message.contains("timeout") {
+        ErrorCode::Timeout
+    } else if ["connect", "dns", "resolve", "unreachable"]
+        .iter()
+        .any(|needle| message.contains(needle))
+    {
+        ErrorCode::Unavailable
+    } else {
+        ErrorCode::Other
+    }
+}
+
+/// Build the error for a response with a non-2xx status
+fn status_error(url: &str, status: u16, body: &str) -> AppError {
+    AppError {
+        code: error_code_for_status(status),
+        message: format!(
+            "API request failed with status: {} - URL: {} - Response: {}",
+            status, url, body
+        ),
+    }
+}
+
+/// Build the error for a request that never produced a response
+fn request_error(url: &str, err: RequestError) -> AppError {
+    let RequestError::Other(message) = err;
+
+    AppError {
+        code: error_code_for_request_error(&message),
+        message: format!("Request failed - URL: {} - Error: {}", url, message),
+    }
+}
@@ -0,0 +1,318 @@
+use super::retry::random_u64;
+use crate::standout::app::{
+    file::FileData,
+    types::{AppError, ErrorCode},
+};
+use serde::Serialize;
+
+/// A `multipart/form-data` body
+///
+/// Send it with `ApiRequest::multipart`, which sets the `Content-Type`
+/// header with the boundary and sends the encoded body through `body-bytes`.
+///
+/// ```ignore
+/// let file = file::normalize(&input.file_url, None, None)?;
+/// let form = Multipart::new()
+///     .text("title", "Invoice")
+///     .json("metadata", &input.metadata)?
+///     .file_data("file", &file)?;
+///
+/// client.request(Method::Post, "/uploads").multipart(form).send()?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct Multipart {
+    boundary: String,
+    content_type: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug)]
+struct Part {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<String>,
+    data: Vec<u8>,
+}
+
+impl Default for Multipart {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Multipart {
+    /// An empty form with a random boundary
+    pub fn new() -> Self {
+        let boundary = new_boundary();
+        Multipart {
+            content_type: format!("multipart/form-data; boundary={}", boundary),
+            boundary,
+            parts: Vec::new(),
+        }
+    }
+
+    /// Add a text field
+    #[allow(dead_code)]
+    pub fn text(self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.part(Part {
+            name: name.into(),
+            filename: None,
+            content_type: None,
+            data: value.into().into_bytes(),
+        })
+    }
+
+    /// Add a field holding any `Serialize` value as `application/json`
+    #[allow(dead_code)]
+    pub fn json<T: Serialize + ?Sized>(
+        self,
+        name: impl Into<String>,
+        value: &T,
+    ) -> Result<Self, AppError> {
+        let data = serde_json::to_vec(value).map_err(|e| AppError {
+            code: ErrorCode::InternalError,
+            message: format!("Failed to serialize multipart field: {}", e),
+        })?;
+
+        Ok(self.part(Part {
+            name: name.into(),
+            filename: None,
+            content_type: Some("application/json".to_string()),
+            data,
+        }))
+    }
+
+    /// Add a file from raw bytes
+    #[allow(dead_code)]
+    pub fn file(
+        self,
+        name: impl Into<String>,
+        filename: impl Into<String>,
+        content_type: impl Into<String>,
+        data: Vec<u8>,
+    ) -> Self {
+        self.part(Part {
+            name: name.into(),
+            filename: Some(filename.into()),
+            content_type: Some(content_type.into()),
+            data,
+        })
+    }
+
+    /// Add a file returned by `file::normalize`
+    #[allow(dead_code)]
+    pub fn file_data(self, name: impl Into<String>, file: &FileData) -> Result<Self, AppError> {
+        let data = crate::base64::decode(&file.base64).map_err(|e| AppError {
+            code: ErrorCode::Other,
+            message: format!("Invalid base64 in file {}: {}", file.filename, e),
+        })?;
+
+        Ok(self.file(name, file.filename.clone(), file.content_type.clone(), data))
+    }
+
+    /// The `Content-Type` header value, including the boundary
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    /// The encoded body
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::with_capacity(
+            self.parts
+                .iter()
+                .map(|part| part.data.len() + 256)
+                .sum::<usize>(),
+        );
+
+        for part in &self.parts {
+            body.extend_from_slice(format!("--{}\r\n", self.boundary).as_bytes());
+
+            let mut disposition = format!(
+                "Content-Disposition: form-data; name=\"{}\"",
+                escape(&part.name)
+            );
+            if let Some(filename) = &part.filename {
+                disposition.push_str(&format!("; filename=\"{}\"", escape(filename)));
+            }
+            body.extend_from_slice(disposition.as_bytes());
+            body.extend_from_slice(b"\r\n");
+
+            if let Some(content_type) = &part.content_type {
+                body.extend_from_slice(
+                    format!("Content-Type: {}\r\n", escape(content_type)).as_bytes(),
+                );
+            }
+
+            body.extend_from_slice(b"\r\n");
+            body.extend_from_slice(&part.data);
+            body.extend_from_slice(b"\r\n");
+        }
+
+        body.extend_from_slice(format!("--{}--\r\n", self.boundary).as_bytes());
+        body
+    }
+
+    /// Field names and sizes, for traces that must not show the contents
+    pub fn describe(&self) -> String {
+        let parts = self
+            .parts
+            .iter()
+            .map(|part| format!("{} ({} bytes)", part.name, part.data.len()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("<multipart: {}>", parts)
+    }
+
+    fn part(mut self, part: Part) -> Self {
+        self.parts.push(part);
+
+        // A boundary must never appear inside a part
+        while self
+            .parts
+            .iter()
+            .any(|part| contains(&part.data, self.boundary.as_bytes()))
+        {
+            self.boundary = new_boundary();
+            self.content_type = format!("multipart/form-data; boundary={}", self.boundary);
+        }
+
+        self
+    }
+}
+
+/// A boundary with 128 random bits
+fn new_boundary() -> String {
+    format!("standout-{:016x}{:016x}", random_u64(), random_u64())
+}
+
+/// Quote-safe header parameter, with `"` and line breaks percent-encoded
+fn escape(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::ApiClient;
+    use crate::harness::{self, mock, received_requests};
+    use crate::standout::app::http::Method;
+    use serde_json::json;
+
+    /// An empty form with a known boundary
+    fn empty_form(boundary: &str) -> Multipart {
+        Multipart {
+            boundary: boundary.to_string(),
+            content_type: format!("multipart/form-data; boundary={}", boundary),
+            parts: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn parts_are_encoded_with_crlfs_and_a_closing_boundary() {
+        let form = empty_form("b0")
+            .text("title", "Invoice \"Q1\"")
+            .json("meta", &json!({ "paid": true }))
+            .unwrap()
+            .file("file", "a\"b.txt", "text/plain", b"line 1\nline 2".to_vec());
+
+        assert_eq!(form.content_type(), "multipart/form-data; boundary=b0");
+        assert_eq!(
+            String::from_utf8(form.encode()).unwrap(),
+            "--b0\r\n\
+             Content-Disposition: form-data; name=\"title\"\r\n\
+             \r\n\
+             Invoice \"Q1\"\r\n\
+             --b0\r\n\
+             Content-Disposition: form-data; name=\"meta\"\r\n\
+             Content-Type: application/json\r\n\
+             \r\n\
+             {\"paid\":true}\r\n\
+             --b0\r\n\
+             Content-Disposition: form-data; name=\"file\"; filename=\"a%22b.txt\"\r\n\
+             Content-Type: text/plain\r\n\
+             \r\n\
+             line 1\nline 2\r\n\
+             --b0--\r\n"
+        );
+        assert_eq!(
+            form.describe(),
+            "<multipart: title (12 bytes), meta (13 bytes), file (13 bytes)>"
+        );
+        assert_eq!(empty_form("b0").encode(), b"--b0--\r\n");
+    }
+
+    #[test]
+    fn boundaries_found_in_a_part_are_replaced() {
+        let form = empty_form("b0").text("note", "contains --b0 inside");
+
+        assert_ne!(form.boundary, "b0");
+        assert!(form.boundary.starts_with("standout-"));
+        assert_eq!(
+            form.content_type(),
+            format!("multipart/form-data; boundary={}", form.boundary)
+        );
+        assert!(!contains(&form.parts[0].data, form.boundary.as_bytes()));
+
+        // Later parts are checked against the boundary in use too
+        let boundary = form.boundary.clone();
+        let form = form.text("echo", format!("{} again", boundary));
+        assert_ne!(form.boundary, boundary);
+    }
+
+    #[test]
+    fn file_data_is_decoded_from_base64() {
+        let file = FileData {
+            base64: crate::base64::encode(&[0, 159, 146, 150]),
+            content_type: "application/octet-stream".to_string(),
+            filename: "blob.bin".to_string(),
+        };
+        let form = empty_form("b0").file_data("upload", &file).unwrap();
+        let part = &form.parts[0];
+        assert_eq!(part.data, [0, 159, 146, 150]);
+        assert_eq!(part.filename.as_deref(), Some("blob.bin"));
+        assert_eq!(
+            part.content_type.as_deref(),
+            Some("application/octet-stream")
+        );
+
+        let invalid = FileData {
+            base64: "not base64!".to_string(),
+            ..file
+        };
+        let err = Multipart::new().file_data("upload", &invalid).unwrap_err();
+        assert!(err.message.starts_with("Invalid base64 in file blob.bin"));
+    }
+
+    #[test]
+    fn requests_carry_the_boundary_in_their_content_type() {
+        harness::reset();
+        mock(Method::Post, "/uploads").respond_json(201, &json!({ "id": 1 }));
+
+        let client = ApiClient::new(&json!({
+            "base_url": "https://api.example.com",
+            "headers": {},
+        }))
+        .unwrap();
+        let form = Multipart::new().file("file", "a.bin", "application/octet-stream", vec![255, 0]);
+        let body = form.encode();
+        let content_type = form.content_type().to_string();
+        client
+            .request(Method::Post, "/uploads")
+            .multipart(form)
+            .send()
+            .unwrap();
+
+        let received = &received_requests()[0];
+        assert_eq!(received.header("Content-Type"), Some(content_type.as_str()));
+        assert_eq!(received.body, body);
+    }
+}
@@ -0,0 +1,372 @@
+use super::{ApiClient, ApiResponse, request_error, status_error};
+use crate::standout::app::{
+    http::Method,
+    types::{AppError, ErrorCode},
+};
+use serde_json::Value;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Tokens are refreshed this long before they expire
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+/// How the access token is obtained from the token endpoint
+#[derive(Clone, Debug, PartialEq)]
+pub enum GrantType {
+    ClientCredentials,
+    RefreshToken,
+}
+
+/// The `oauth2` block of the connection data
+///
+/// ```json
+/// {
+///   "token_url": "https://auth.example.com/oauth/token",
+///   "client_id": "my-client",
+///   "client_secret": "secret",
+///   "grant_type": "client_credentials",
+///   "scope": "read write"
+/// }
+/// ```
+///
+/// `grant_type` defaults to `refresh_token` when a `refresh_token` is present
+/// and to `client_credentials` otherwise. An optional `access_token` is used
+/// until the API rejects it.
+#[derive(Clone, Debug)]
+pub struct OAuth2Config {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub grant_type: GrantType,
+    pub refresh_token: Option<String>,
+    pub scope: Option<String>,
+    pub access_token: Option<String>,
+}
+
+impl OAuth2Config {
+    /// Read the config from an `oauth2` block of the connection data
+    pub fn from_value(oauth2: &Value) -> Result<Self, AppError> {
+        let field = |name: &str| {
+            oauth2
+                .get(name)
+                .and_then(|v| v.as_str())
+                .map(str::to_string)
+        };
+        let required = |name: &str| {
+            field(name).ok_or_else(|| AppError {
+                code: ErrorCode::Misconfigured,
+                message: format!("oauth2.{} not found in connection data", name),
+            })
+        };
+
+        let refresh_token = field("refresh_token");
+        let grant_type = match field("grant_type").as_deref() {
+            Some("client_credentials") => GrantType::ClientCredentials,
+            Some("refresh_token") => GrantType::RefreshToken,
+            None if refresh_token.is_some() => GrantType::RefreshToken,
+            None => GrantType::ClientCredentials,
+            Some(other) => {
+                return Err(AppError {
+                    code: ErrorCode::Misconfigured,
+                    message: format!("Unsupported oauth2.grant_type: {}", other),
+                });
+            }
+        };
+
+        if grant_type == GrantType::RefreshToken && refresh_token.is_none() {
+            return Err(AppError {
+                code: ErrorCode::Misconfigured,
+                message: "oauth2.refresh_token not found in connection data".to_string(),
+            });
+        }
+
+        Ok(OAuth2Config {
+            token_url: required("token_url")?,
+            client_id: required("client_id")?,
+            client_secret: field("client_secret"),
+            grant_type,
+            refresh_token,
+            scope: field("scope"),
+            access_token: field("access_token"),
+        })
+    }
+}
+
+/// A token minted by the token endpoint
+#[derive(Clone, Debug)]
+struct Token {
+    access_token: String,
+    expires_at: Option<Instant>,
+}
+
+/// Mints, caches and refreshes the access token of an ApiClient
+///
+/// The token lives as long as the client, which is one action or trigger
+/// invocation. A rotated refresh token is used for later refreshes within
+/// that invocation.
+#[derive(Debug)]
+pub struct OAuth2 {
+    config: OAuth2Config,
+    token: Mutex<Option<Token>>,
+    refresh_token: Mutex<Option<String>>,
+}
+
+impl OAuth2 {
+    pub fn new(config: OAuth2Config) -> Self {
+        let token = config.access_token.clone().map(|access_token| Token {
+            access_token,
+            expires_at: None,
+        });
+        let refresh_token = config.refresh_token.clone();
+
+        OAuth2 {
+            config,
+            token: Mutex::new(token),
+            refresh_token: Mutex::new(refresh_token),
+        }
+    }
+
+    /// The current access token, requesting one if there is none or it has expired
+    pub fn access_token(&self, client: &ApiClient) -> Result<String, AppError> {
+        let cached = self.lock_token().clone();
+        match cached {
+            Some(token) if token.expires_at.is_none_or(|at| Instant::now() < at) => {
+                Ok(token.access_token)
+            }
+            _ => self.refresh(client),
+        }
+    }
+
+    /// Request a new access token from the token endpoint
+    pub fn refresh(&self, client: &ApiClient) -> Result<String, AppError> {
+        let mut form = vec![("client_id", self.config.client_id.clone())];
+
+        match self.config.grant_type {
+            GrantType::ClientCredentials => {
+                form.push(("grant_type", "client_credentials".to_string()));
+            }
+            GrantType::RefreshToken => {
+                let refresh_token = self
+                    .refresh_token
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .clone()
+                    .unwrap_or_default();
+                form.push(("grant_type", "refresh_token".to_string()));
+                form.push(("refresh_token", refresh_token));
+            }
+        }
+        if let Some(client_secret) = &self.config.client_secret {
+            form.push(("client_secret", client_secret.clone()));
+        }
+        if let Some(scope) = &self.config.scope {
+            form.push(("scope", scope.clone()));
+        }
+
+        // The token endpoint must not see the API's default headers
+        let token_client = client.without_auth();
+        let request = token_client
+            .request(Method::Post, &self.config.token_url)
+            .header("Accept", "application/json")
+            .form(form);
+        let url = request.url();
+
+        let response: ApiResponse = request
+            .send_once(&url)?
+            .map_err(|err| request_error(&url, err))?
+            .into();
+
+        match response.status {
+            200..=299 => {}
+            // invalid_client, invalid_grant and friends
+            400 | 401 => {
+                return Err(AppError {
+                    code: ErrorCode::Unauthenticated,
+                    message: format!(
+                        "OAuth2 token request failed with status: {} - URL: {} - Response: {}",
+                        response.status, url, response.body
+                    ),
+                });
+            }
+            status => return Err(status_error(&url, status, &response.body)),
+        }
+        let response = token_client.decode(&response)?;
+
+        let access_token = response
+            .get("access_token")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| AppError {
+                code: ErrorCode::MalformedResponse,
+                message: "OAuth2 token response missing 'access_token' field".to_string(),
+            })?
+            .to_string();
+
+        let expires_in = response.get("expires_in").and_then(|v| match v {
+            Value::Number(n) => n.as_u64(),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        });
+
+        if let Some(refresh_token) = response.get("refresh_token").and_then(|v| v.as_str()) {
+            *self.refresh_token.lock().unwrap_or_else(|e| e.into_inner()) =
+                Some(refresh_token.to_string());
+        }
+
+        *self.lock_token() = Some(Token {
+            access_token: access_token.clone(),
+            // An `expires_in` too large to add to the clock never expires
+            expires_at: expires_in.and_then(|seconds| {
+                Instant::now()
+                    .checked_add(Duration::from_secs(seconds).saturating_sub(EXPIRY_MARGIN))
+            }),
+        });
+
+        Ok(access_token)
+    }
+
+    fn lock_token(&self) -> std::sync::MutexGuard<'_, Option<Token>> {
+        self.token.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::ApiClient;
+    use crate::harness::{self, mock, received_requests};
+    use crate::standout::app::{http::Method, types::ErrorCode};
+    use serde_json::{Value, json};
+
+    const TOKEN_URL: &str = "https://auth.example.com/oauth/token";
+
+    fn client(oauth2: Value) -> ApiClient {
+        ApiClient::new(&json!({ "base_url": "https://api.example.com", "oauth2": oauth2 })).unwrap()
+    }
+
+    fn token(access_token: &str) -> Value {
+        json!({ "access_token": access_token, "token_type": "Bearer", "expires_in": 3600 })
+    }
+
+    #[test]
+    fn client_credentials_are_exchanged_for_a_token() {
+        harness::reset();
+        mock(Method::Post, TOKEN_URL).respond_json(200, &token("minted"));
+        mock(Method::Get, "/items").respond_json(200, &json!([]));
+
+        let client = client(json!({
+            "token_url": TOKEN_URL,
+            "client_id": "my-client",
+            "client_secret": "secret",
+            "scope": "read"
+        }));
+        client.get("/items").unwrap();
+        client.get("/items").unwrap();
+
+        let requests = received_requests();
+        assert_eq!(requests.len(), 3);
+        let form = requests[0].text();
+        assert!(form.contains("grant_type=client_credentials"));
+        assert!(form.contains("client_id=my-client"));
+        assert!(form.contains("client_secret=secret"));
+        assert!(form.contains("scope=read"));
+        assert_eq!(requests[0].header("Authorization"), None);
+        // The token is minted once and reused
+        assert_eq!(requests[1].header("Authorization"), Some("Bearer minted"));
+        assert_eq!(requests[2].header("Authorization"), Some("Bearer minted"));
+    }
+
+    #[test]
+    fn rejected_tokens_are_refreshed_and_the_request_retried_once() {
+        harness::reset();
+        mock(Method::Post, TOKEN_URL).respond_json(200, &token("fresh"));
+        mock(Method::Get, "/items").respond(401, "");
+        mock(Method::Get, "/items")
+            .with_header("Authorization", "Bearer fresh")
+            .respond_json(200, &json!([]));
+
+        let client = client(json!({
+            "token_url": TOKEN_URL,
+            "client_id": "my-client",
+            "refresh_token": "original",
+            "access_token": "stale"
+        }));
+        assert_eq!(client.get("/items").unwrap(), json!([]));
+
+        let requests = received_requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].header("Authorization"), Some("Bearer stale"));
+        assert!(requests[1].text().contains("grant_type=refresh_token"));
+        assert!(requests[1].text().contains("refresh_token=original"));
+        assert_eq!(requests[2].header("Authorization"), Some("Bearer fresh"));
+
+        // A token that is still rejected after the refresh is not refreshed again
+        harness::reset();
+        mock(Method::Post, TOKEN_URL).respond_json(200, &token("fresh"));
+        mock(Method::Get, "/items").respond(401, "");
+        let err = client.get("/items").unwrap_err();
+        assert!(matches!(err.code, ErrorCode::Unauthenticated));
+        assert_eq!(received_requests().len(), 3);
+    }
+
+    #[test]
+    fn rotated_refresh_tokens_are_used_for_the_next_refresh() {
+        harness::reset();
+        mock(Method::Post, TOKEN_URL).respond_json(
+            200,
+            &json!({ "access_token": "second", "refresh_token": "rotated-again" }),
+        );
+        mock(Method::Post, TOKEN_URL).times(1).respond_json(
+            200,
+            &json!({ "access_token": "first", "refresh_token": "rotated" }),
+        );
+        mock(Method::Get, "/items").respond_json(200, &json!([]));
+        mock(Method::Get, "/items")
+            .with_header("Authorization", "Bearer first")
+            .times(1)
+            .respond(401, "");
+
+        let client = client(json!({
+            "token_url": TOKEN_URL,
+            "client_id": "my-client",
+            "refresh_token": "original"
+        }));
+        client.get("/items").unwrap();
+
+        let refreshes: Vec<String> = received_requests()
+            .iter()
+            .filter(|request| request.url == TOKEN_URL)
+            .map(|request| request.text())
+            .collect();
+        assert_eq!(refreshes.len(), 2);
+        assert!(refreshes[0].contains("refresh_token=original"));
+        assert!(refreshes[1].contains("refresh_token=rotated"));
+    }
+
+    #[test]
+    fn token_endpoint_rejections_are_unauthenticated() {
+        for status in [400, 401] {
+            harness::reset();
+            mock(Method::Post, TOKEN_URL)
+                .respond_json(status, &json!({ "error": "invalid_client" }));
+
+            let client = client(json!({ "token_url": TOKEN_URL, "client_id": "my-client" }));
+            let err = client.get("/items").unwrap_err();
+            assert!(matches!(err.code, ErrorCode::Unauthenticated), "{}", status);
+            assert!(err.message.contains("invalid_client"));
+        }
+    }
+
+    #[test]
+    fn huge_expires_in_never_expires() {
+        harness::reset();
+        mock(Method::Post, TOKEN_URL).respond_json(
+            200,
+            &json!({ "access_token": "forever", "expires_in": u64::MAX }),
+        );
+        mock(Method::Get, "/items").respond_json(200, &json!([]));
+
+        let client = client(json!({ "token_url": TOKEN_URL, "client_id": "my-client" }));
+        client.get("/items").unwrap();
+        client.get("/items").unwrap();
+        assert_eq!(received_requests().len(), 3);
+    }
+}
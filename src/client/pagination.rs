@@ -0,0 +1,495 @@
+use super::{ApiClient, url};
+use crate::standout::app::{
+    http::Method,
+    types::{AppError, ErrorCode},
+};
+use serde_json::Value;
+use std::collections::VecDeque;
+
+/// How a paginated endpoint points to its next page
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub enum Pagination {
+    /// Follow the RFC 8288 `Link: <...>; rel="next"` response header
+    LinkHeader,
+    /// Send the cursor found at the JSON pointer `cursor_path` of each page
+    /// (e.g. `/meta/next_cursor`) as the `param` query parameter
+    Cursor { param: String, cursor_path: String },
+    /// Send `offset_param` and `limit_param`, advancing the offset by `limit`
+    Offset {
+        offset_param: String,
+        limit_param: String,
+        limit: u64,
+    },
+    /// Send `page_param` and `per_page_param`, starting at `first_page`
+    PageNumber {
+        page_param: String,
+        per_page_param: String,
+        per_page: u64,
+        first_page: u64,
+    },
+}
+
+/// One page of items, with the token of the page after it
+pub(super) struct Page {
+    pub items: Vec<Value>,
+    /// `None` if this is the last page
+    pub next_token: Option<String>,
+}
+
+/// Requests the pages of a paginated source for `Pages`
+pub(super) trait PageSource {
+    /// Request the page identified by `token`, `None` for the first page
+    fn fetch_page(&self, token: Option<&str>) -> Result<Page, AppError>;
+
+    /// Token to resume at the item after the first `consumed` of the page identified by `token`
+    ///
+    /// Defaults to the page itself, so the consumed items are yielded again.
+    fn resume_token(&self, token: Option<&str>, _consumed: usize) -> Option<String> {
+        token.map(str::to_string)
+    }
+}
+
+/// Lazily yields the items of a `PageSource`, one page at a time
+///
+/// The iteration shared by `Paginator` and `graphql::RelayPaginator`: a page
+/// is only requested once the items of the previous page have been consumed.
+pub(super) struct Pages<S> {
+    pub source: S,
+    pub max_items: Option<usize>,
+    yielded: usize,
+    buffer: VecDeque<Value>,
+    /// Token of the page currently in `buffer`, `None` for the first page
+    pub page_token: Option<String>,
+    /// Token of the page after the current one, `None` if it is the last page
+    next_token: Option<String>,
+    /// Number of items on the current page
+    page_len: usize,
+    started: bool,
+}
+
+impl<S: PageSource> Pages<S> {
+    pub fn new(source: S) -> Self {
+        Pages {
+            source,
+            max_items: None,
+            yielded: 0,
+            buffer: VecDeque::new(),
+            page_token: None,
+            next_token: None,
+            page_len: 0,
+            started: false,
+        }
+    }
+
+    /// Token to continue after the items yielded so far, see `Paginator::continuation`
+    pub fn continuation(&self) -> Option<String> {
+        if !self.started {
+            return self.page_token.clone();
+        }
+
+        if self.buffer.is_empty() {
+            return self.next_token.clone();
+        }
+
+        let consumed = self.page_len - self.buffer.len();
+        self.source
+            .resume_token(self.page_token.as_deref(), consumed)
+    }
+
+    /// Whether every page has been requested and every item yielded
+    pub fn is_exhausted(&self) -> bool {
+        self.started && self.buffer.is_empty() && self.next_token.is_none()
+    }
+
+    /// Request the page identified by `page_token` and fill the buffer
+    fn fetch_page(&mut self) -> Result<(), AppError> {
+        let page = self.source.fetch_page(self.page_token.as_deref())?;
+
+        // An empty page never leads anywhere, whatever the API says
+        self.next_token = page.next_token.filter(|_| !page.items.is_empty());
+        self.page_len = page.items.len();
+        self.buffer = page.items.into();
+        self.started = true;
+        Ok(())
+    }
+}
+
+impl<S: PageSource> Iterator for Pages<S> {
+    type Item = Result<Value, AppError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.max_items.is_some_and(|max| self.yielded >= max) {
+            return None;
+        }
+
+        while self.buffer.is_empty() {
+            if self.started {
+                // Move on to the next page, if there is one
+                self.page_token = Some(self.next_token.take()?);
+            }
+
+            if let Err(err) = self.fetch_page() {
+                // Stop after an error instead of requesting the same page again
+                self.started = true;
+                self.buffer.clear();
+                self.next_token = None;
+                return Some(Err(err));
+            }
+        }
+
+        self.yielded += 1;
+        self.buffer.pop_front().map(Ok)
+    }
+}
+
+/// Lazily yields the items of a paginated endpoint, one page at a time
+///
+/// Created with `ApiClient::paginate`. A page is only requested once the
+/// items of the previous page have been consumed.
+///
+/// ```ignore
+/// let mut issues = client
+///     .paginate("/issues", Pagination::LinkHeader)
+///     .query("state", "open")
+///     .max_items(100);
+///
+/// if let Some(token) = store.cursor.take() {
+///     issues = issues.resume_from(token);
+/// }
+///
+/// let items = issues.by_ref().collect::<Result<Vec<_>, _>>()?;
+/// store.cursor = issues.continuation();
+/// ```
+pub struct Paginator<'a> {
+    pages: Pages<EndpointPages<'a>>,
+}
+
+impl<'a> Paginator<'a> {
+    pub(super) fn new(client: &'a ApiClient, endpoint: &str, pagination: Pagination) -> Self {
+        Paginator {
+            pages: Pages::new(EndpointPages {
+                client,
+                endpoint: endpoint.to_string(),
+                query: Vec::new(),
+                pagination,
+                items_path: String::new(),
+            }),
+        }
+    }
+
+    /// Add a query parameter sent with every page
+    #[allow(dead_code)]
+    pub fn query(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.pages.source.query.push((key.into(), value.into()));
+        self
+    }
+
+    /// JSON pointer to the items array of a page, e.g. `/data` (defaults to the root)
+    #[allow(dead_code)]
+    pub fn items_at(mut self, path: impl Into<String>) -> Self {
+        self.pages.source.items_path = path.into();
+        self
+    }
+
+    /// Stop after yielding this many items
+    #[allow(dead_code)]
+    pub fn max_items(mut self, max_items: usize) -> Self {
+        self.pages.max_items = Some(max_items);
+        self
+    }
+
+    /// Start from a token previously returned by `continuation`
+    #[allow(dead_code)]
+    pub fn resume_from(mut self, token: impl Into<String>) -> Self {
+        self.pages.page_token = Some(token.into());
+        self
+    }
+
+    /// Token to pass to `resume_from` to continue after the items yielded so far
+    ///
+    /// When iteration stops in the middle of a page, the token points at that
+    /// page, so some items may be yielded again when resuming. Only offset
+    /// pagination can resume at the exact item. Returns `None` when all pages
+    /// have been read, or when no token identifies the current page (the
+    /// first page of link header and cursor pagination).
+    #[allow(dead_code)]
+    pub fn continuation(&self) -> Option<String> {
+        self.pages.continuation()
+    }
+
+    /// Whether every page has been requested and every item yielded
+    #[allow(dead_code)]
+    pub fn is_exhausted(&self) -> bool {
+        self.pages.is_exhausted()
+    }
+}
+
+impl Iterator for Paginator<'_> {
+    type Item = Result<Value, AppError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.pages.next()
+    }
+}
+
+/// The pages of a GET endpoint, found with a `Pagination` strategy
+struct EndpointPages<'a> {
+    client: &'a ApiClient,
+    endpoint: String,
+    query: Vec<(String, String)>,
+    pagination: Pagination,
+    items_path: String,
+}
+
+/// The number in a page token, or `default` for the first page
+fn token_number(token: Option<&str>, default: u64) -> u64 {
+    token
+        .and_then(|token| token.parse().ok())
+        .unwrap_or(default)
+}
+
+impl PageSource for EndpointPages<'_> {
+    fn fetch_page(&self, token: Option<&str>) -> Result<Page, AppError> {
+        let request = match (&self.pagination, token) {
+            // The next link already carries the query of the original request
+            (Pagination::LinkHeader, Some(url)) => self.client.request(Method::Get, url),
+            _ => {
+                let request = self
+                    .client
+                    .request(Method::Get, &self.endpoint)
+                    .query_map(self.query.clone());
+
+                match &self.pagination {
+                    Pagination::LinkHeader => request,
+                    Pagination::Cursor { param, .. } => match token {
+                        Some(cursor) => request.query(param, cursor),
+                        None => request,
+                    },
+                    Pagination::Offset {
+                        offset_param,
+                        limit_param,
+                        limit,
+                    } => request
+                        .query(offset_param, token_number(token, 0).to_string())
+                        .query(limit_param, limit.to_string()),
+                    Pagination::PageNumber {
+                        page_param,
+                        per_page_param,
+                        per_page,
+                        first_page,
+                    } => request
+                        .query(page_param, token_number(token, *first_page).to_string())
+                        .query(per_page_param, per_page.to_string()),
+                }
+            }
+        };
+
+        let url = request.url();
+        let response = request.send_with_retries()?;
+        let page = self.client.decode(&response)?;
+
+        let items = page
+            .pointer(&self.items_path)
+            .and_then(|items| items.as_array())
+            .ok_or_else(|| AppError {
+                code: ErrorCode::MalformedResponse,
+                message: format!(
+                    "No items array at '{}' in response - URL: {}",
+                    self.items_path, url
+                ),
+            })?;
+
+        let page_len = items.len() as u64;
+        let next_token = match &self.pagination {
+            Pagination::LinkHeader => response
+                .headers
+                .get_all("link")
+                .find_map(next_link)
+                .map(|link| url::resolve(&url, &link)),
+            Pagination::Cursor { cursor_path, .. } => match page.pointer(cursor_path) {
+                Some(Value::String(cursor)) if !cursor.is_empty() => Some(cursor.clone()),
+                Some(Value::Number(cursor)) => Some(cursor.to_string()),
+                _ => None,
+            },
+            Pagination::Offset { limit, .. } => {
+                (page_len >= *limit).then(|| (token_number(token, 0) + page_len).to_string())
+            }
+            Pagination::PageNumber {
+                per_page,
+                first_page,
+                ..
+            } => {
+                (page_len >= *per_page).then(|| (token_number(token, *first_page) + 1).to_string())
+            }
+        };
+
+        Ok(Page {
+            items: items.clone(),
+            next_token,
+        })
+    }
+
+    fn resume_token(&self, token: Option<&str>, consumed: usize) -> Option<String> {
+        match &self.pagination {
+            Pagination::Offset { .. } => {
+                Some((token_number(token, 0) + consumed as u64).to_string())
+            }
+            _ => token.map(str::to_string),
+        }
+    }
+}
+
+/// The target of the `rel="next"` link in an RFC 8288 `Link` header value
+fn next_link(header: &str) -> Option<String> {
+    let mut rest = header;
+
+    while let Some(start) = rest.find('<') {
+        let end = start + rest[start..].find('>')?;
+        let target = &rest[start + 1..end];
+        let params_end = rest[end..].find('<').map_or(rest.len(), |i| end + i);
+
+        let is_next = rest[end + 1..params_end].split(';').any(|param| {
+            let Some((name, value)) = param.split_once('=') else {
+                return false;
+            };
+            name.trim().eq_ignore_ascii_case("rel")
+                && value
+                    .trim()
+                    .trim_end_matches(',')
+                    .trim_matches('"')
+                    .split_whitespace()
+                    .any(|rel| rel.eq_ignore_ascii_case("next"))
+        });
+
+        if is_next {
+            return Some(target.to_string());
+        }
+        rest = &rest[params_end..];
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness;
+    use serde_json::json;
+
+    #[test]
+    fn finds_the_next_link_among_others() {
+        let header = r#"<https://api.example.com/items?page=1>; rel="first", <https://api.example.com/items?page=3>; title="a, b; c"; rel="prefetch next", <https://api.example.com/items?page=9>; rel=last"#;
+        assert_eq!(
+            next_link(header).as_deref(),
+            Some("https://api.example.com/items?page=3")
+        );
+
+        assert_eq!(
+            next_link("</items?page=2>;REL=next").as_deref(),
+            Some("/items?page=2")
+        );
+        assert_eq!(
+            next_link(r#"<https://api.example.com/items?page=1>; rel="prev""#),
+            None
+        );
+        assert_eq!(next_link("garbage"), None);
+    }
+
+    #[test]
+    fn resolves_relative_links_against_the_page() {
+        let page = "https://api.example.com/v1/items?page=1";
+        let cases = [
+            (
+                "https://other.example.com/items",
+                "https://other.example.com/items",
+            ),
+            (
+                "/v2/items?page=2",
+                "https://api.example.com/v2/items?page=2",
+            ),
+            ("items?page=2", "https://api.example.com/v1/items?page=2"),
+            ("?page=2", "https://api.example.com/v1/items?page=2"),
+            (
+                "../v2/items?page=2",
+                "https://api.example.com/v2/items?page=2",
+            ),
+            ("./items?page=2", "https://api.example.com/v1/items?page=2"),
+        ];
+        for (link, expected) in cases {
+            assert_eq!(url::resolve(page, link), expected);
+        }
+
+        assert_eq!(
+            url::resolve("https://api.example.com?page=1", "items?page=2"),
+            "https://api.example.com/items?page=2"
+        );
+    }
+
+    #[test]
+    fn follows_link_headers_until_the_last_page() {
+        harness::reset();
+        harness::mock(Method::Get, "/v1/items?page=2").respond_json(200, &json!([{ "id": 3 }]));
+        harness::mock(Method::Get, "/v1/items")
+            .times(1)
+            .response_header("Link", r#"<items?page=2>; rel="next""#)
+            .respond_json(200, &json!([{ "id": 1 }, { "id": 2 }]));
+
+        let client = ApiClient::new(&json!({
+            "base_url": "https://api.example.com/v1",
+            "headers": {},
+        }))
+        .unwrap();
+        let items = client
+            .paginate("/items", Pagination::LinkHeader)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            items,
+            [json!({ "id": 1 }), json!({ "id": 2 }), json!({ "id": 3 })]
+        );
+        let received = harness::received_requests();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[1].url, "https://api.example.com/v1/items?page=2");
+    }
+
+    #[test]
+    fn offset_pages_resume_at_the_exact_item() {
+        harness::reset();
+        harness::mock(Method::Get, "/items?offset=0").respond_json(200, &json!([1, 2]));
+        harness::mock(Method::Get, "/items?offset=2").respond_json(200, &json!([3]));
+
+        let client = ApiClient::new(&json!({
+            "base_url": "https://api.example.com",
+            "headers": {},
+        }))
+        .unwrap();
+        let offset = || Pagination::Offset {
+            offset_param: "offset".to_string(),
+            limit_param: "limit".to_string(),
+            limit: 2,
+        };
+
+        let mut items = client.paginate("/items", offset());
+        assert_eq!(items.next().unwrap().unwrap(), json!(1));
+        assert_eq!(items.continuation().as_deref(), Some("1"));
+        assert_eq!(items.next().unwrap().unwrap(), json!(2));
+        assert_eq!(items.continuation().as_deref(), Some("2"));
+
+        let rest = client
+            .paginate("/items", offset())
+            .resume_from("2")
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(rest, [json!(3)]);
+        assert_eq!(
+            harness::received_requests()
+                .last()
+                .unwrap()
+                .query("limit")
+                .as_deref(),
+            Some("2")
+        );
+    }
+}
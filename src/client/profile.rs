@@ -0,0 +1,344 @@
+use super::auth::render_template;
+use super::{RateLimiter, url};
+use crate::json::lookup;
+use crate::standout::app::types::{AppError, ErrorCode};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Where ApiClient finds its settings in the connection data
+///
+/// Declared in `connection.toml` (or `connection.yaml`) at the root of the
+/// connector and embedded by `build.rs`. Without a profile file the defaults
+/// apply: `base_url` and `headers` are read from the top level of the
+/// connection data.
+///
+/// ```toml
+/// base_url_path = "api_endpoint"
+/// headers_path = ""
+///
+/// [default_headers]
+/// Content-Type = "application/json"
+///
+/// [headers]
+/// X-Account = "{{auth.account}}"
+///
+/// [auth]
+/// type = "bearer"
+/// token = "{{auth.token}}"
+///
+/// [rate_limit]
+/// requests = 10
+/// per_seconds = 1
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConnectionProfile {
+    /// URI template of the base URL, e.g. `https://{subdomain}.example.com`,
+    /// filled from the connection data; replaces `base_url_path`
+    ///
+    /// Variables take single braces so their values are percent-encoded;
+    /// `build.rs` rejects the `{{path}}` placeholders used elsewhere.
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Dotted path to the base URL in the connection data
+    #[serde(default = "default_base_url_path")]
+    pub base_url_path: String,
+    /// Dotted path to an object of literal headers, empty to not read one
+    #[serde(default = "default_headers_path")]
+    pub headers_path: String,
+    /// Header templates with `{{path}}` placeholders into the connection data
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Headers sent with every request, overridden by the ones above
+    #[serde(default)]
+    pub default_headers: BTreeMap<String, String>,
+    /// Auth strategy as in the connection's `auth` field, with `{{path}}`
+    /// placeholders in its values
+    #[serde(default)]
+    pub auth: Option<Value>,
+    /// Default client-side rate limit, see `RateLimiter::from_value`
+    #[serde(default)]
+    pub rate_limit: Option<Value>,
+}
+
+fn default_base_url_path() -> String {
+    "base_url".to_string()
+}
+
+fn default_headers_path() -> String {
+    "headers".to_string()
+}
+
+impl Default for ConnectionProfile {
+    fn default() -> Self {
+        ConnectionProfile {
+            base_url: None,
+            base_url_path: default_base_url_path(),
+            headers_path: default_headers_path(),
+            headers: BTreeMap::new(),
+            default_headers: BTreeMap::new(),
+            auth: None,
+            rate_limit: None,
+        }
+    }
+}
+
+impl ConnectionProfile {
+    /// The profile embedded at build time, or the defaults without a profile file
+    pub fn embedded() -> Result<Self, AppError> {
+        if crate::CONNECTION_PROFILE_JSON.is_empty() {
+            return Ok(Self::default());
+        }
+
+        serde_json::from_str(crate::CONNECTION_PROFILE_JSON).map_err(|e| AppError {
+            code: ErrorCode::Misconfigured,
+            message: format!("Invalid connection profile: {}", e),
+        })
+    }
+
+    /// The base URL from the connection data
+    ///
+    /// The URL may be a URI template such as `https://{subdomain}.example.com`,
+    /// whose variables are dotted paths into the connection data.
+    pub fn base_url(&self, connection_data: &Value) -> Result<String, AppError> {
+        let base_url = match &self.base_url {
+            Some(template) => template.as_str(),
+            None => lookup(connection_data, &self.base_url_path)
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| AppError {
+                    code: ErrorCode::Misconfigured,
+                    message: format!("{} not found in connection data", self.base_url_path),
+                })?,
+        };
+
+        url::expand(base_url, connection_data)
+    }
+
+    /// The default headers, the connection's literal headers and the rendered
+    /// header templates, in that order of precedence
+    ///
+    /// Without an auth strategy and any headers declared by the profile, the
+    /// literal headers object is required.
+    pub fn headers(
+        &self,
+        connection_data: &Value,
+        has_auth: bool,
+    ) -> Result<Vec<(String, String)>, AppError> {
+        let mut headers: Vec<(String, String)> = self
+            .default_headers
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+
+        let literal_headers = match self.headers_path.as_str() {
+            "" => None,
+            path => lookup(connection_data, path).and_then(|v| v.as_object()),
+        };
+
+        match literal_headers {
+            Some(literal_headers) => {
+                for (key, value) in literal_headers {
+                    if let Some(header_value) = value.as_str() {
+                        set_header(&mut headers, key, header_value.to_string());
+                    }
+                }
+            }
+            None if !self.headers_path.is_empty()
+                && !has_auth
+                && self.headers.is_empty()
+                && self.default_headers.is_empty() =>
+            {
+                return Err(AppError {
+                    code: ErrorCode::Misconfigured,
+                    message: "Headers not found in connection data".to_string(),
+                });
+            }
+            None => {}
+        }
+
+        for (key, template) in &self.headers {
+            set_header(
+                &mut headers,
+                key,
+                render_template(template, connection_data)?,
+            );
+        }
+
+        Ok(headers)
+    }
+
+    /// The rate limiter from the connection's `rate_limit` block, falling
+    /// back to the profile's
+    pub fn rate_limiter(&self, connection_data: &Value) -> Result<Option<RateLimiter>, AppError> {
+        connection_data
+            .get("rate_limit")
+            .or(self.rate_limit.as_ref())
+            .map(RateLimiter::from_value)
+            .transpose()
+    }
+
+    /// The profile's auth block with its placeholders filled in
+    pub fn auth(&self, connection_data: &Value) -> Result<Option<Value>, AppError> {
+        self.auth
+            .as_ref()
+            .map(|auth| render_value(auth, connection_data))
+            .transpose()
+    }
+}
+
+/// Replace a header case-insensitively
+fn set_header(headers: &mut Vec<(String, String)>, key: &str, value: String) {
+    headers.retain(|(existing, _)| !existing.eq_ignore_ascii_case(key));
+    headers.push((key.to_string(), value));
+}
+
+/// Render every string in a JSON value as a template
+fn render_value(value: &Value, connection_data: &Value) -> Result<Value, AppError> {
+    Ok(match value {
+        Value::String(template) => Value::String(render_template(template, connection_data)?),
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| render_value(item, connection_data))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, field)| Ok((key.clone(), render_value(field, connection_data)?)))
+                .collect::<Result<_, AppError>>()?,
+        ),
+        other => other.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn profile(profile: Value) -> ConnectionProfile {
+        serde_json::from_value(profile).unwrap()
+    }
+
+    fn header<'a>(headers: &'a [(String, String)], key: &str) -> Option<&'a str> {
+        headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn defaults_read_the_top_level() {
+        let profile = ConnectionProfile::default();
+        let data = json!({ "base_url": "https://api.example.com", "headers": { "X-Key": "k1", "X-Num": 1 } });
+
+        assert_eq!(profile.base_url(&data).unwrap(), "https://api.example.com");
+        assert_eq!(
+            profile.headers(&data, false).unwrap(),
+            [("X-Key".to_string(), "k1".to_string())]
+        );
+
+        let err = profile.base_url(&json!({})).unwrap_err();
+        assert!(matches!(err.code, ErrorCode::Misconfigured));
+        assert_eq!(err.message, "base_url not found in connection data");
+
+        // Headers are only optional when something else authenticates
+        let err = profile.headers(&json!({}), false).unwrap_err();
+        assert_eq!(err.message, "Headers not found in connection data");
+        assert!(profile.headers(&json!({}), true).unwrap().is_empty());
+    }
+
+    #[test]
+    fn base_urls_are_found_by_path_or_expanded_from_a_template() {
+        let data = json!({ "api": { "endpoint": "https://eu.example.com" }, "subdomain": "a b" });
+
+        let by_path = profile(json!({ "base_url_path": "api.endpoint" }));
+        assert_eq!(by_path.base_url(&data).unwrap(), "https://eu.example.com");
+
+        let template = profile(json!({ "base_url": "https://{subdomain}.example.com/v2" }));
+        assert_eq!(
+            template.base_url(&data).unwrap(),
+            "https://a%20b.example.com/v2"
+        );
+        assert!(template.base_url(&json!({})).is_err());
+    }
+
+    #[test]
+    fn header_templates_override_literal_and_default_headers() {
+        let profile = profile(json!({
+            "headers_path": "settings.headers",
+            "default_headers": { "Accept": "application/json", "X-Account": "default" },
+            "headers": { "x-account": "{{auth.account}}", "X-Version": "{{version}}" },
+        }));
+        let data = json!({
+            "settings": { "headers": { "ACCEPT": "text/csv", "X-Account": "literal" } },
+            "auth": { "account": "acme" },
+            "version": 2,
+        });
+
+        let headers = profile.headers(&data, false).unwrap();
+        assert_eq!(headers.len(), 3);
+        assert_eq!(header(&headers, "Accept"), Some("text/csv"));
+        assert_eq!(header(&headers, "X-Account"), Some("acme"));
+        assert_eq!(header(&headers, "X-Version"), Some("2"));
+
+        let err = profile
+            .headers(&json!({ "version": 2 }), false)
+            .unwrap_err();
+        assert!(matches!(err.code, ErrorCode::Misconfigured));
+        assert_eq!(err.message, "auth.account not found in connection data");
+    }
+
+    #[test]
+    fn auth_blocks_are_rendered_recursively() {
+        let profile = profile(json!({
+            "headers_path": "",
+            "auth": {
+                "type": "api_key",
+                "key": "{{credentials.key}}",
+                "scopes": ["{{credentials.scope}}", "read"],
+                "retries": 2,
+            },
+        }));
+        let data = json!({ "credentials": { "key": "k1", "scope": "write" } });
+
+        assert_eq!(
+            profile.auth(&data).unwrap(),
+            Some(json!({
+                "type": "api_key",
+                "key": "k1",
+                "scopes": ["write", "read"],
+                "retries": 2,
+            }))
+        );
+        assert_eq!(ConnectionProfile::default().auth(&data).unwrap(), None);
+    }
+
+    #[test]
+    fn the_connection_rate_limit_wins_over_the_profile() {
+        let profile = profile(json!({ "rate_limit": { "requests": 10 } }));
+
+        assert!(profile.rate_limiter(&json!({})).unwrap().is_some());
+        assert!(
+            ConnectionProfile::default()
+                .rate_limiter(&json!({}))
+                .unwrap()
+                .is_none()
+        );
+
+        let err = profile
+            .rate_limiter(&json!({ "rate_limit": { "requests": -1 } }))
+            .unwrap_err();
+        assert_eq!(err.message, "rate_limit.requests must be a positive number");
+    }
+
+    #[test]
+    fn unknown_settings_are_rejected() {
+        let err = serde_json::from_value::<ConnectionProfile>(json!({ "base_ur": "x" }));
+        assert!(err.is_err());
+        let err = serde_json::from_value::<ConnectionProfile>(json!({ "headers": { "X": 1 } }));
+        assert!(err.is_err());
+    }
+}
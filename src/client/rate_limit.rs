@@ -0,0 +1,180 @@
+use crate::deadline;
+use crate::standout::app::types::{AppError, ErrorCode};
+use serde_json::Value;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Token bucket that spaces out the requests of an ApiClient
+///
+/// Holds up to `burst` tokens and refills `requests` tokens every `per`.
+/// Every request attempt takes one token and waits for it when the bucket is
+/// empty. The limiter is shared by clones of the client, so every request of
+/// one invocation counts against the same bucket. A wait that would run into
+/// the invocation budget fails with `ErrorCode::Timeout` instead.
+#[derive(Debug)]
+pub struct RateLimiter {
+    requests: f64,
+    per: Duration,
+    burst: u32,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// May go negative while requests wait for tokens they already reserved
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    /// Allow `requests` requests every `per`, with bursts of up to `requests`
+    #[allow(dead_code)]
+    pub fn new(requests: u32, per: Duration) -> Self {
+        Self::with_burst(requests, per, requests)
+    }
+
+    /// Allow `requests` requests every `per`, with bursts of up to `burst`
+    pub fn with_burst(requests: u32, per: Duration, burst: u32) -> Self {
+        Self::with_rate(f64::from(requests.max(1)), per, burst)
+    }
+
+    /// `requests` may be a fraction, e.g. 0.5 for one request every other `per`
+    fn with_rate(requests: f64, per: Duration, burst: u32) -> Self {
+        let burst = burst.max(1);
+
+        RateLimiter {
+            requests,
+            per,
+            burst,
+            bucket: Mutex::new(Bucket {
+                tokens: f64::from(burst),
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Read a `rate_limit` block such as `{"requests": 10, "per_seconds": 1}`
+    ///
+    /// `per_seconds` defaults to 1 and `burst` to `requests`. `requests` may
+    /// be a fraction, so `{"requests": 0.5}` allows one request every 2s.
+    pub fn from_value(rate_limit: &Value) -> Result<Self, AppError> {
+        let number = |name: &str| {
+            rate_limit.get(name).map(|v| {
+                v.as_f64().filter(|n| *n > 0.0).ok_or_else(|| AppError {
+                    code: ErrorCode::Misconfigured,
+                    message: format!("rate_limit.{} must be a positive number", name),
+                })
+            })
+        };
+
+        let requests = number("requests").ok_or_else(|| AppError {
+            code: ErrorCode::Misconfigured,
+            message: "rate_limit.requests not found".to_string(),
+        })??;
+        let per_seconds = number("per_seconds").transpose()?.unwrap_or(1.0);
+        let burst = number("burst").transpose()?.unwrap_or(requests);
+
+        let per = Duration::try_from_secs_f64(per_seconds).map_err(|_| AppError {
+            code: ErrorCode::Misconfigured,
+            message: format!("rate_limit.per_seconds is out of range: {}", per_seconds),
+        })?;
+
+        // A bucket holds whole tokens, and at least the one a request needs
+        Ok(Self::with_rate(
+            requests,
+            per,
+            burst.floor().min(f64::from(u32::MAX)) as u32,
+        ))
+    }
+
+    /// Take a token, waiting for one when the bucket is empty
+    pub fn acquire(&self) -> Result<(), AppError> {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.refilled_at).as_secs_f64() * self.rate();
+            bucket.tokens = (bucket.tokens + refill).min(f64::from(self.burst));
+            bucket.refilled_at = now;
+
+            let wait = if bucket.tokens >= 1.0 {
+                Duration::ZERO
+            } else {
+                // Rates too slow to represent wait forever, i.e. time out
+                Duration::try_from_secs_f64((1.0 - bucket.tokens) / self.rate())
+                    .unwrap_or(Duration::MAX)
+            };
+
+            if !deadline::allows(wait) {
+                return Err(AppError {
+                    code: ErrorCode::Timeout,
+                    message: format!(
+                        "Waiting {}ms for the rate limit of {} requests per {}s would exceed the invocation budget",
+                        wait.as_millis(),
+                        self.requests,
+                        self.per.as_secs_f64()
+                    ),
+                });
+            }
+
+            // Reserve the token now so later callers queue up behind this one
+            bucket.tokens -= 1.0;
+            wait
+        };
+
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+        Ok(())
+    }
+
+    /// Tokens added per second
+    fn rate(&self) -> f64 {
+        self.requests / self.per.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn fractional_rates_are_kept() {
+        let limiter = RateLimiter::from_value(&json!({ "requests": 0.5 })).unwrap();
+        assert_eq!(limiter.rate(), 0.5);
+        assert_eq!(limiter.burst, 1);
+
+        let limiter =
+            RateLimiter::from_value(&json!({ "requests": 3, "per_seconds": 2, "burst": 5.9 }))
+                .unwrap();
+        assert_eq!(limiter.rate(), 1.5);
+        assert_eq!(limiter.burst, 5);
+    }
+
+    #[test]
+    fn invalid_values_are_misconfigured() {
+        for rate_limit in [
+            json!({ "requests": 1, "per_seconds": 1e20 }),
+            json!({ "requests": 0 }),
+            json!({ "requests": "10" }),
+            json!({ "per_seconds": 1 }),
+        ] {
+            let error = RateLimiter::from_value(&rate_limit).unwrap_err();
+            assert!(
+                matches!(error.code, ErrorCode::Misconfigured),
+                "{}",
+                rate_limit
+            );
+        }
+    }
+
+    #[test]
+    fn burst_is_free_then_requests_wait() {
+        let limiter = RateLimiter::with_burst(1, Duration::from_millis(50), 2);
+        let started = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire().unwrap();
+        }
+        assert!(started.elapsed() >= Duration::from_millis(40));
+    }
+}
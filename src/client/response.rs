@@ -0,0 +1,102 @@
+use crate::standout::app::{http::Response, types::AppError};
+use serde_json::Value;
+
+/// HTTP headers with case-insensitive lookup
+///
+/// Keeps the headers in the order the host returned them, including repeated
+/// headers such as `Link` or `Set-Cookie`.
+#[derive(Clone, Debug, Default)]
+pub struct Headers {
+    entries: Vec<(String, String)>,
+}
+
+impl Headers {
+    /// The first value of the header, if present
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Every value of the header, in order
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether the header is present
+    #[allow(dead_code)]
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// All headers as name and value pairs
+    #[allow(dead_code)]
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+}
+
+impl From<Vec<(String, String)>> for Headers {
+    fn from(entries: Vec<(String, String)>) -> Self {
+        Headers { entries }
+    }
+}
+
+/// A response received by ApiClient
+///
+/// Returned by `ApiRequest::response` and the `*_response` helpers when the
+/// status, headers or binary body are needed and not only the parsed JSON.
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct ApiResponse {
+    pub status: u16,
+    pub headers: Headers,
+    pub body: String,
+    /// Raw payload for binary responses, `None` for text-only responses
+    pub body_bytes: Option<Vec<u8>>,
+}
+
+impl ApiResponse {
+    /// The first value of a header, looked up case-insensitively
+    #[allow(dead_code)]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// The binary body, falling back to the bytes of the text body
+    #[allow(dead_code)]
+    pub fn bytes(&self) -> &[u8] {
+        self.body_bytes.as_deref().unwrap_or(self.body.as_bytes())
+    }
+
+    /// Decode the body with the built-in decoders, based on its `Content-Type`
+    ///
+    /// Use `ApiClient::decode` to include decoders registered on the client.
+    #[allow(dead_code)]
+    pub fn json(&self) -> Result<Value, AppError> {
+        super::body::decode(self, &super::body::default_decoders())
+    }
+
+    /// The body deserialized into `T` with the built-in decoders
+    #[allow(dead_code)]
+    pub fn json_as<T: serde::de::DeserializeOwned>(&self) -> Result<T, AppError> {
+        super::typed::from_value(self.json()?)
+    }
+}
+
+impl From<Response> for ApiResponse {
+    fn from(response: Response) -> Self {
+        ApiResponse {
+            status: response.status,
+            headers: response.headers.into(),
+            body: response.body,
+            body_bytes: response.body_bytes,
+        }
+    }
+}
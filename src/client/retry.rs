@@ -0,0 +1,198 @@
+use super::Headers;
+use crate::standout::app::http::Method;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// Retry policy for transient failures in ApiClient
+///
+/// Failed attempts are retried with jittered exponential backoff. A
+/// `Retry-After` or `X-RateLimit-Reset` header on the response takes
+/// precedence over the computed backoff. A retry is only attempted when it
+/// finishes within `max_elapsed`, which keeps the whole call inside the 30
+/// second limit the platform puts on `execute` and `fetch-events`.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one
+    pub max_attempts: u32,
+    /// Backoff before the first retry, doubled for every following retry
+    pub base_delay: Duration,
+    /// Upper bound for the computed backoff
+    pub max_delay: Duration,
+    /// Time budget for the call including all attempts and waits
+    pub max_elapsed: Duration,
+    /// Response statuses that are considered transient
+    pub retry_statuses: Vec<u16>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(8),
+            max_elapsed: Duration::from_secs(20),
+            retry_statuses: vec![429, 502, 503, 504],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries
+    #[allow(dead_code)]
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Whether a response with the given status should be retried
+    pub fn retries_status(&self, status: u16) -> bool {
+        self.retry_statuses.contains(&status)
+    }
+
+    /// Jittered backoff before the given retry (1 for the first retry)
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)));
+        let ceiling = exponential.min(self.max_delay);
+
+        // Full jitter: anywhere between zero and the exponential ceiling
+        let millis = ceiling.as_millis() as u64;
+        if millis == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_millis(random_u64() % (millis + 1))
+    }
+}
+
+/// Whether the method can be sent again without side effects
+pub fn is_idempotent(method: &Method) -> bool {
+    !matches!(method, Method::Post | Method::Patch)
+}
+
+/// The wait requested by the server through `Retry-After` or `X-RateLimit-Reset`
+pub fn requested_delay(headers: &Headers) -> Option<Duration> {
+    if let Some(value) = headers.get("Retry-After").map(str::trim) {
+        // Either delay-seconds or an HTTP-date
+        if let Ok(seconds) = value.parse::<u64>() {
+            return Some(Duration::from_secs(seconds));
+        }
+        if let Ok(date) = chrono::DateTime::parse_from_rfc2822(value) {
+            return Some(until_timestamp(date.timestamp()));
+        }
+    }
+
+    if let Some(value) = headers.get("X-RateLimit-Reset").map(str::trim)
+        && let Ok(reset) = value.parse::<f64>()
+    {
+        // Vendors use either an epoch timestamp or seconds until the reset
+        return Some(if reset > 1_000_000_000.0 {
+            until_timestamp(reset.ceil() as i64)
+        } else {
+            Duration::from_secs_f64(reset.max(0.0))
+        });
+    }
+
+    None
+}
+
+/// Time left until a Unix timestamp, zero if it has already passed
+fn until_timestamp(timestamp: i64) -> Duration {
+    let seconds = timestamp - chrono::Utc::now().timestamp();
+    Duration::from_secs(seconds.max(0) as u64)
+}
+
+/// A random number from the randomly seeded std hasher
+pub fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::ApiClient;
+    use crate::harness::{self, mock, received_requests};
+    use serde_json::json;
+    use std::time::Instant;
+
+    fn client() -> ApiClient {
+        ApiClient::new(&json!({ "base_url": "https://api.example.com", "headers": {} }))
+            .unwrap()
+            .with_retry_policy(RetryPolicy {
+                base_delay: Duration::from_millis(1),
+                ..RetryPolicy::default()
+            })
+    }
+
+    #[test]
+    fn transient_statuses_are_retried() {
+        harness::reset();
+        mock(Method::Get, "/items").respond_json(200, &json!([]));
+        mock(Method::Get, "/items").times(2).respond(503, "");
+
+        assert_eq!(client().get("/items").unwrap(), json!([]));
+        assert_eq!(received_requests().len(), 3);
+    }
+
+    #[test]
+    fn retry_after_is_honored() {
+        harness::reset();
+        mock(Method::Get, "/items").respond_json(200, &json!([]));
+        mock(Method::Get, "/items")
+            .times(1)
+            .response_header("Retry-After", "1")
+            .respond(429, "");
+
+        let started = Instant::now();
+        client().get("/items").unwrap();
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        assert_eq!(received_requests().len(), 2);
+    }
+
+    #[test]
+    fn posts_are_only_retried_when_idempotent() {
+        harness::reset();
+        mock(Method::Post, "/items").respond_json(201, &json!({ "id": 1 }));
+        mock(Method::Post, "/items").times(1).respond(503, "");
+
+        let client = client();
+        assert!(client.post("/items", &json!({})).is_err());
+        assert_eq!(received_requests().len(), 1);
+
+        mock(Method::Post, "/items").times(1).respond(503, "");
+        let created = client
+            .request(Method::Post, "/items")
+            .body(&json!({}))
+            .idempotent()
+            .send()
+            .unwrap();
+        assert_eq!(created["id"], 1);
+        assert_eq!(received_requests().len(), 3);
+    }
+
+    #[test]
+    fn waits_past_max_elapsed_are_not_made() {
+        harness::reset();
+        mock(Method::Get, "/items").respond_json(200, &json!([]));
+        mock(Method::Get, "/items")
+            .times(1)
+            .response_header("Retry-After", "18446744073709551615")
+            .respond(503, "");
+        mock(Method::Get, "/slow")
+            .response_header("Retry-After", "5")
+            .respond(503, "");
+
+        let client = client().with_retry_policy(RetryPolicy {
+            max_elapsed: Duration::from_secs(2),
+            ..RetryPolicy::default()
+        });
+        let started = Instant::now();
+        assert!(client.get("/items").is_err());
+        assert!(client.get("/slow").is_err());
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(received_requests().len(), 2);
+    }
+}
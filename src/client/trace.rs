@@ -0,0 +1,473 @@
+use super::body::{RequestBody, encode_form};
+use crate::standout::app::{
+    environment,
+    http::{Method, RequestError, Response},
+};
+use crate::wire::method_name;
+use serde_json::{Map, Value, json};
+use std::time::Duration;
+
+/// Set to `1` or `true` to trace every exchange to stderr
+pub const TRACE_VAR: &str = "STANDOUT_HTTP_TRACE";
+
+/// Comma-separated header, query parameter and JSON field names to redact
+pub const REDACT_VAR: &str = "STANDOUT_HTTP_TRACE_REDACT";
+
+/// Maximum number of bytes of each body written to the trace
+pub const BODY_LIMIT_VAR: &str = "STANDOUT_HTTP_TRACE_BODY_LIMIT";
+
+/// Headers that are redacted no matter what the deny-list says
+const ALWAYS_REDACTED: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+];
+
+/// Names on the deny-list before any are added
+const DEFAULT_DENY_LIST: &[&str] = &[
+    "password",
+    "client_secret",
+    "access_token",
+    "refresh_token",
+    "id_token",
+    "api_key",
+    "apikey",
+    "api-key",
+    "x-api-key",
+    "token",
+    "secret",
+];
+
+const DEFAULT_BODY_LIMIT: usize = 2048;
+
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Replaces credentials in headers, URLs and bodies with `[REDACTED]`
+///
+/// `Authorization`, `Proxy-Authorization`, `Cookie` and `Set-Cookie` are
+/// always redacted. Headers, query parameters and JSON or form fields named
+/// on the deny-list are redacted as well (compared case-insensitively).
+#[derive(Clone, Debug)]
+pub struct Redactor {
+    deny_list: Vec<String>,
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Redactor {
+            deny_list: DEFAULT_DENY_LIST
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl Redactor {
+    /// Add names to the deny-list
+    pub fn redact<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.deny_list.extend(
+            names
+                .into_iter()
+                .map(|name| name.as_ref().to_ascii_lowercase()),
+        );
+        self
+    }
+
+    pub fn is_denied(&self, name: &str) -> bool {
+        let name = name.to_ascii_lowercase();
+        ALWAYS_REDACTED.contains(&name.as_str()) || self.deny_list.contains(&name)
+    }
+
+    /// The headers with denied and `credentials` values redacted
+    pub fn headers(
+        &self,
+        headers: &[(String, String)],
+        credentials: &[String],
+    ) -> Vec<(String, String)> {
+        headers
+            .iter()
+            .map(|(key, value)| {
+                let is_credential = credentials
+                    .iter()
+                    .any(|name| name.eq_ignore_ascii_case(key));
+                let value = if is_credential || self.is_denied(key) {
+                    REDACTED
+                } else {
+                    value
+                };
+                (key.clone(), value.to_string())
+            })
+            .collect()
+    }
+
+    /// The URL with denied query parameters redacted
+    pub fn url(&self, url: &str) -> String {
+        let Some((base, query)) = url.split_once('?') else {
+            return url.to_string();
+        };
+
+        format!("{}?{}", base, self.form(query))
+    }
+
+    pub fn json(&self, value: &mut Value) {
+        match value {
+            Value::Object(fields) => {
+                for (key, field) in fields.iter_mut() {
+                    if self.is_denied(key) {
+                        *field = json!(REDACTED);
+                    } else {
+                        self.json(field);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.json(item)),
+            _ => {}
+        }
+    }
+
+    pub fn form(&self, form: &str) -> String {
+        form.split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((key, _)) if self.is_denied(&decode_component(key)) => {
+                    format!("{}={}", key, REDACTED)
+                }
+                _ => pair.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+
+    /// Text that holds JSON or a form, redacted; other text as is
+    pub fn text(&self, text: &str, content_type: &str) -> String {
+        if let Ok(mut value) = serde_json::from_str::<Value>(text) {
+            let original = value.clone();
+            self.json(&mut value);
+            if value != original {
+                return value.to_string();
+            }
+        } else if content_type
+            .to_ascii_lowercase()
+            .starts_with("application/x-www-form-urlencoded")
+        {
+            return self.form(text);
+        }
+        text.to_string()
+    }
+}
+
+/// Writes one JSON line per HTTP exchange of an ApiClient to stderr
+///
+/// Off unless the `STANDOUT_HTTP_TRACE` environment variable is set.
+/// Credentials are redacted by a `Redactor`.
+#[derive(Clone, Debug)]
+pub struct Tracer {
+    enabled: bool,
+    redactor: Redactor,
+    body_limit: usize,
+}
+
+impl Default for Tracer {
+    fn default() -> Self {
+        Tracer {
+            enabled: false,
+            redactor: Redactor::default(),
+            body_limit: DEFAULT_BODY_LIMIT,
+        }
+    }
+}
+
+impl Tracer {
+    /// Read the tracing settings from the environment
+    pub fn from_env() -> Self {
+        let mut tracer = Tracer {
+            enabled: environment::env_var(TRACE_VAR).is_some_and(|value| {
+                matches!(
+                    value.trim().to_ascii_lowercase().as_str(),
+                    "1" | "true" | "yes" | "on"
+                )
+            }),
+            ..Tracer::default()
+        };
+
+        if !tracer.enabled {
+            return tracer;
+        }
+
+        if let Some(names) = environment::env_var(REDACT_VAR) {
+            tracer = tracer.redact(names.split(',').map(str::trim).filter(|n| !n.is_empty()));
+        }
+        if let Some(limit) = environment::env_var(BODY_LIMIT_VAR).and_then(|v| v.parse().ok()) {
+            tracer.body_limit = limit;
+        }
+
+        tracer
+    }
+
+    /// Add names to the deny-list
+    pub fn redact<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.redactor = self.redactor.redact(names);
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Trace one attempt of a request
+    ///
+    /// `url` should not carry credentials; `credential_headers` are the
+    /// headers set by the auth strategy, which are always redacted.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(
+        &self,
+        method: Method,
+        url: &str,
+        headers: &[(String, String)],
+        credential_headers: &[String],
+        body: Option<&RequestBody>,
+        outcome: &Result<Response, RequestError>,
+        duration: Duration,
+    ) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut line = json!({
+            "event": "http",
+            "method": method_name(method),
+            "url": self.redactor.url(url),
+            "duration_ms": duration.as_millis() as u64,
+            "request": {
+                "headers": self.redact_headers(headers, credential_headers),
+                "body": body.map(|body| self.request_body(body)),
+            },
+        });
+
+        match outcome {
+            Ok(response) => {
+                line["status"] = json!(response.status);
+                line["response"] = json!({
+                    "headers": self.redact_headers(&response.headers, &[]),
+                    "body": self.response_body(response),
+                });
+            }
+            Err(RequestError::Other(message)) => {
+                line["error"] = json!(message);
+            }
+        }
+
+        eprintln!("{}", line);
+    }
+
+    fn redact_headers(&self, headers: &[(String, String)], credentials: &[String]) -> Value {
+        let headers = self.redactor.headers(headers, credentials);
+        Value::Object(
+            headers
+                .into_iter()
+                .map(|(key, value)| (key, json!(value)))
+                .collect::<Map<_, _>>(),
+        )
+    }
+
+    fn request_body(&self, body: &RequestBody) -> String {
+        match body {
+            RequestBody::Json(value) => {
+                let mut value = value.clone();
+                self.redactor.json(&mut value);
+                self.truncate(value.to_string())
+            }
+            RequestBody::Form(fields) => self.truncate(self.redactor.form(&encode_form(fields))),
+            RequestBody::Text {
+                content,
+                content_type,
+            } => self.truncate(self.redactor.text(content, content_type)),
+            RequestBody::Bytes { data, content_type } => {
+                format!("<{} bytes of {}>", data.len(), content_type)
+            }
+            RequestBody::Multipart(form) => form.describe(),
+        }
+    }
+
+    fn response_body(&self, response: &Response) -> String {
+        if response.body.is_empty()
+            && let Some(bytes) = &response.body_bytes
+            && !bytes.is_empty()
+        {
+            return format!("<{} bytes>", bytes.len());
+        }
+
+        let content_type = response
+            .headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("Content-Type"))
+            .map(|(_, value)| value.as_str())
+            .unwrap_or_default();
+
+        self.truncate(self.redactor.text(&response.body, content_type))
+    }
+
+    fn truncate(&self, mut text: String) -> String {
+        if text.len() <= self.body_limit {
+            return text;
+        }
+
+        let mut end = self.body_limit;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        let truncated = text.len() - end;
+        text.truncate(end);
+        text.push_str(&format!("... ({} more bytes)", truncated));
+        text
+    }
+}
+
+fn decode_component(component: &str) -> String {
+    urlencoding::decode(&component.replace('+', " "))
+        .map(|decoded| decoded.into_owned())
+        .unwrap_or_else(|_| component.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn values(headers: &[(String, String)]) -> Vec<&str> {
+        headers.iter().map(|(_, value)| value.as_str()).collect()
+    }
+
+    #[test]
+    fn authorization_and_cookies_are_always_redacted() {
+        // Even with nothing else on the deny-list
+        let redactor = Redactor {
+            deny_list: Vec::new(),
+        };
+        let headers = redactor.headers(
+            &pairs(&[
+                ("authorization", "Bearer a1"),
+                ("Proxy-Authorization", "Basic b2"),
+                ("Cookie", "session=s3"),
+                ("SET-COOKIE", "session=s4"),
+                ("Accept", "application/json"),
+            ]),
+            &[],
+        );
+        assert_eq!(
+            values(&headers),
+            [REDACTED, REDACTED, REDACTED, REDACTED, "application/json"]
+        );
+    }
+
+    #[test]
+    fn credential_headers_of_the_auth_strategy_are_redacted() {
+        let headers = Redactor::default().headers(
+            &pairs(&[("X-Shop-Token", "t1"), ("X-Request-Id", "r1")]),
+            &["x-shop-token".to_string()],
+        );
+        assert_eq!(values(&headers), [REDACTED, "r1"]);
+    }
+
+    #[test]
+    fn denied_json_and_form_fields_are_redacted() {
+        let redactor = Redactor::default().redact(["Email"]);
+
+        let mut body = json!({
+            "user": { "email": "ada@example.com", "name": "Ada" },
+            "credentials": [{ "Password": "p1", "api_key": "k1" }],
+            "token": { "nested": "replaced whole" },
+        });
+        redactor.json(&mut body);
+        assert_eq!(
+            body,
+            json!({
+                "user": { "email": REDACTED, "name": "Ada" },
+                "credentials": [{ "Password": REDACTED, "api_key": REDACTED }],
+                "token": REDACTED,
+            })
+        );
+
+        assert_eq!(
+            redactor.form("grant_type=password&client%5Fsecret=s1&password=p1&scope=a+b"),
+            "grant_type=password&client%5Fsecret=[REDACTED]&password=[REDACTED]&scope=a+b"
+        );
+        assert_eq!(
+            redactor.url("https://api.example.com/items?API_KEY=k1&page=2"),
+            "https://api.example.com/items?API_KEY=[REDACTED]&page=2"
+        );
+        assert_eq!(
+            redactor.text(
+                "refresh_token=r1",
+                "application/x-www-form-urlencoded; charset=utf-8"
+            ),
+            "refresh_token=[REDACTED]"
+        );
+        // Bodies with nothing to redact are traced as sent
+        assert_eq!(
+            redactor.text(r#"{"b":1,  "a":2}"#, "application/json"),
+            r#"{"b":1,  "a":2}"#
+        );
+    }
+
+    #[test]
+    fn request_bodies_are_redacted_by_kind() {
+        let tracer = Tracer::default();
+
+        assert_eq!(
+            tracer.request_body(&RequestBody::Json(json!({ "secret": "s1", "id": 1 }))),
+            r#"{"id":1,"secret":"[REDACTED]"}"#
+        );
+        assert_eq!(
+            tracer.request_body(&RequestBody::Form(pairs(&[
+                ("client_id", "app"),
+                ("client_secret", "s1"),
+            ]))),
+            "client_id=app&client_secret=[REDACTED]"
+        );
+        assert_eq!(
+            tracer.request_body(&RequestBody::Bytes {
+                data: vec![0; 3],
+                content_type: "image/png".to_string(),
+            }),
+            "<3 bytes of image/png>"
+        );
+    }
+
+    #[test]
+    fn truncation_stays_on_a_char_boundary() {
+        let tracer = Tracer {
+            body_limit: 5,
+            ..Tracer::default()
+        };
+
+        // "é" is two bytes, so byte 5 falls inside the third one
+        assert_eq!(tracer.truncate("éééé".to_string()), "éé... (4 more bytes)");
+        assert_eq!(
+            tracer.truncate("abcd€".to_string()),
+            "abcd... (3 more bytes)"
+        );
+        assert_eq!(tracer.truncate("abcde".to_string()), "abcde");
+
+        let tracer = Tracer {
+            body_limit: 0,
+            ..Tracer::default()
+        };
+        assert_eq!(tracer.truncate("é".to_string()), "... (2 more bytes)");
+    }
+}
@@ -0,0 +1,74 @@
+use crate::standout::app::types::{AppError, ErrorCode};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+
+/// Serialize a request body into JSON
+pub fn to_value<B: Serialize + ?Sized>(body: &B) -> Result<Value, AppError> {
+    serde_json::to_value(body).map_err(|e| AppError {
+        code: ErrorCode::InternalError,
+        message: format!("Failed to serialize request body: {}", e),
+    })
+}
+
+/// Deserialize a response body, naming the JSON path that did not match `T`
+///
+/// The path is written like `items[2].owner.id`, or `.` for the whole body.
+pub fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, AppError> {
+    serde_path_to_error::deserialize(value).map_err(|e| AppError {
+        code: ErrorCode::MalformedResponse,
+        message: format!(
+            "Failed to deserialize {} at {}: {}",
+            std::any::type_name::<T>(),
+            e.path(),
+            e.inner()
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Page {
+        items: Vec<Item>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Item {
+        owner: Owner,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Owner {
+        id: u64,
+    }
+
+    #[test]
+    fn mismatches_name_their_json_path() {
+        let body = json!({ "items": [
+            { "owner": { "id": 1 } },
+            { "owner": { "id": 2 } },
+            { "owner": { "id": "three" } },
+        ] });
+        let err = from_value::<Page>(body).unwrap_err();
+
+        assert!(matches!(err.code, ErrorCode::MalformedResponse));
+        assert!(
+            err.message
+                .contains(" at items[2].owner.id: invalid type: string \"three\""),
+            "{}",
+            err.message
+        );
+        assert!(err.message.starts_with("Failed to deserialize "));
+        assert!(err.message.contains("::Page at "));
+
+        let err = from_value::<Page>(json!([])).unwrap_err();
+        assert!(err.message.contains(" at .: "), "{}", err.message);
+    }
+}
@@ -0,0 +1,587 @@
+//! RFC 6570 URI templates, URL joining and RFC 3986 reference resolution
+//!
+//! Supports the operators of levels 1 to 3 (`{var}`, `{+var}`, `{#var}`,
+//! `{.var}`, `{/var}`, `{;var}`, `{?var}` and `{&var}`) plus prefixes
+//! (`{var:3}`) and exploded lists (`{/segments*}`). Variable names may be
+//! dotted paths into the values, e.g. `{auth.subdomain}`.
+
+use crate::json::lookup;
+use crate::standout::app::types::{AppError, ErrorCode};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::fmt;
+
+/// Expand a URI template with the given values
+///
+/// Values are percent-encoded, so an ID containing `/` or `?` stays inside
+/// its path segment. Only `{+var}` and `{#var}` keep reserved characters.
+/// A missing variable is an error, except in `{?query}` and `{&query}`
+/// expressions where it is left out.
+pub fn expand(template: &str, values: &Value) -> Result<String, AppError> {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}').ok_or_else(|| AppError {
+            code: ErrorCode::Misconfigured,
+            message: format!("Unclosed expression in URL template: {}", template),
+        })? + start;
+
+        expanded.push_str(&rest[..start]);
+        expand_expression(&rest[start + 1..end], values, &mut expanded)?;
+        rest = &rest[end + 1..];
+    }
+
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Join a base URL and an endpoint
+///
+/// An absolute endpoint (`https://...`) is used as is. Otherwise the endpoint
+/// is appended to the base URL with exactly one `/` between them, so
+/// `https://api.example.com/v2/` and `users` give
+/// `https://api.example.com/v2/users`. Endpoints starting with `?` or `#` are
+/// appended without a slash.
+pub fn join(base_url: &str, endpoint: &str) -> String {
+    if is_absolute(endpoint) {
+        return endpoint.to_string();
+    }
+    if endpoint.is_empty() {
+        return base_url.to_string();
+    }
+    if endpoint.starts_with('?') || endpoint.starts_with('#') {
+        return format!("{}{}", base_url, endpoint);
+    }
+    if base_url.is_empty() {
+        return endpoint.to_string();
+    }
+
+    format!(
+        "{}/{}",
+        base_url.trim_end_matches('/'),
+        endpoint.trim_start_matches('/')
+    )
+}
+
+/// Resolve a reference, such as a link found in a response, against the
+/// URL it was found on (RFC 3986, section 5.2)
+///
+/// Unlike `join`, relative references replace the last path segment of the
+/// base and may use `..`, so `?page=2` on `https://api.example.com/v1/items`
+/// gives `https://api.example.com/v1/items?page=2` and `../v2/items` gives
+/// `https://api.example.com/v2/items`.
+pub fn resolve(base_url: &str, reference: &str) -> String {
+    let base = Parts::parse(base_url);
+    let reference = Parts::parse(reference);
+
+    let target = if reference.scheme.is_some() {
+        Parts {
+            path: remove_dot_segments(&reference.path),
+            ..reference
+        }
+    } else if reference.authority.is_some() {
+        Parts {
+            scheme: base.scheme,
+            path: remove_dot_segments(&reference.path),
+            ..reference
+        }
+    } else if reference.path.is_empty() {
+        Parts {
+            scheme: base.scheme,
+            authority: base.authority,
+            path: base.path.clone(),
+            query: reference.query.or(base.query),
+            fragment: reference.fragment,
+        }
+    } else {
+        let path = if reference.path.starts_with('/') {
+            reference.path.into_owned()
+        } else if base.authority.is_some() && base.path.is_empty() {
+            format!("/{}", reference.path)
+        } else {
+            let directory = base.path.rfind('/').map_or("", |i| &base.path[..=i]);
+            format!("{}{}", directory, reference.path)
+        };
+        Parts {
+            scheme: base.scheme,
+            authority: base.authority,
+            path: remove_dot_segments(&path),
+            query: reference.query,
+            fragment: reference.fragment,
+        }
+    };
+
+    target.to_string()
+}
+
+/// The components of a URI reference (RFC 3986, appendix B)
+struct Parts<'a> {
+    scheme: Option<&'a str>,
+    authority: Option<&'a str>,
+    path: Cow<'a, str>,
+    query: Option<&'a str>,
+    fragment: Option<&'a str>,
+}
+
+impl<'a> Parts<'a> {
+    fn parse(uri: &'a str) -> Self {
+        let (rest, fragment) = match uri.split_once('#') {
+            Some((rest, fragment)) => (rest, Some(fragment)),
+            None => (uri, None),
+        };
+        let (rest, query) = match rest.split_once('?') {
+            Some((rest, query)) => (rest, Some(query)),
+            None => (rest, None),
+        };
+        let (scheme, rest) = match rest.split_once(':') {
+            Some((scheme, rest)) if is_scheme(scheme) => (Some(scheme), rest),
+            _ => (None, rest),
+        };
+        let (authority, path) = match rest.strip_prefix("//") {
+            Some(rest) => {
+                let end = rest.find('/').unwrap_or(rest.len());
+                (Some(&rest[..end]), &rest[end..])
+            }
+            None => (None, rest),
+        };
+
+        Parts {
+            scheme,
+            authority,
+            path: Cow::Borrowed(path),
+            query,
+            fragment,
+        }
+    }
+}
+
+impl fmt::Display for Parts<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(scheme) = self.scheme {
+            write!(f, "{}:", scheme)?;
+        }
+        if let Some(authority) = self.authority {
+            write!(f, "//{}", authority)?;
+        }
+        f.write_str(&self.path)?;
+        if let Some(query) = self.query {
+            write!(f, "?{}", query)?;
+        }
+        if let Some(fragment) = self.fragment {
+            write!(f, "#{}", fragment)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_scheme(scheme: &str) -> bool {
+    scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+/// Apply the `.` and `..` segments of a path (RFC 3986, section 5.2.4)
+fn remove_dot_segments(path: &str) -> Cow<'static, str> {
+    let mut input = path;
+    let mut output = String::with_capacity(path.len());
+
+    while !input.is_empty() {
+        if let Some(rest) = input.strip_prefix("../") {
+            input = rest;
+        } else if let Some(rest) = input.strip_prefix("./") {
+            input = rest;
+        } else if input.starts_with("/./") {
+            input = &input[2..];
+        } else if input == "/." {
+            input = "/";
+        } else if input.starts_with("/../") || input == "/.." {
+            input = if input == "/.." { "/" } else { &input[3..] };
+            output.truncate(output.rfind('/').unwrap_or(0));
+        } else if input == "." || input == ".." {
+            input = "";
+        } else {
+            let start = usize::from(input.starts_with('/'));
+            let end = input[start..].find('/').map_or(input.len(), |i| i + start);
+            output.push_str(&input[..end]);
+            input = &input[end..];
+        }
+    }
+
+    Cow::Owned(output)
+}
+
+/// Whether the URL has a scheme such as `https://`
+pub fn is_absolute(url: &str) -> bool {
+    url.split_once("://").is_some_and(|(scheme, _)| {
+        !scheme.is_empty()
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+/// How an operator expands its variables (RFC 6570, appendix A)
+struct Operator {
+    first: &'static str,
+    separator: &'static str,
+    named: bool,
+    if_empty: &'static str,
+    allow_reserved: bool,
+    optional: bool,
+}
+
+impl Operator {
+    fn parse(expression: &str) -> (Operator, &str) {
+        let op = |first, separator, named, if_empty, allow_reserved, optional| Operator {
+            first,
+            separator,
+            named,
+            if_empty,
+            allow_reserved,
+            optional,
+        };
+
+        match expression.chars().next() {
+            Some('+') => (op("", ",", false, "", true, false), &expression[1..]),
+            Some('#') => (op("#", ",", false, "", true, false), &expression[1..]),
+            Some('.') => (op(".", ".", false, "", false, false), &expression[1..]),
+            Some('/') => (op("/", "/", false, "", false, false), &expression[1..]),
+            Some(';') => (op(";", ";", true, "", false, false), &expression[1..]),
+            Some('?') => (op("?", "&", true, "=", false, true), &expression[1..]),
+            Some('&') => (op("&", "&", true, "=", false, true), &expression[1..]),
+            _ => (op("", ",", false, "", false, false), expression),
+        }
+    }
+}
+
+fn expand_expression(
+    expression: &str,
+    values: &Value,
+    expanded: &mut String,
+) -> Result<(), AppError> {
+    let (operator, variables) = Operator::parse(expression);
+    let mut first = true;
+
+    for spec in variables.split(',') {
+        let (name, prefix, explode) = parse_varspec(spec, expression)?;
+
+        let value = lookup(values, name).filter(|value| !value.is_null());
+        let Some(value) = value else {
+            if operator.optional {
+                continue;
+            }
+            return Err(AppError {
+                code: ErrorCode::Misconfigured,
+                message: format!("Missing value for URL template variable: {}", name),
+            });
+        };
+
+        let items: Vec<String> = match value {
+            Value::Array(items) => items.iter().filter_map(scalar).collect(),
+            Value::Object(_) => {
+                return Err(AppError {
+                    code: ErrorCode::Misconfigured,
+                    message: format!("URL template variable {} cannot be an object", name),
+                });
+            }
+            value => scalar(value).into_iter().collect(),
+        };
+        if items.is_empty() && value.is_array() {
+            continue;
+        }
+
+        expanded.push_str(if first {
+            operator.first
+        } else {
+            operator.separator
+        });
+        first = false;
+
+        let encode = |item: &str| encode(item, operator.allow_reserved);
+        let named = |expanded: &mut String, encoded: String| {
+            expanded.push_str(&encode(name));
+            if encoded.is_empty() {
+                expanded.push_str(operator.if_empty);
+            } else {
+                expanded.push('=');
+                expanded.push_str(&encoded);
+            }
+        };
+
+        if value.is_array() {
+            if explode {
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        expanded.push_str(operator.separator);
+                    }
+                    if operator.named {
+                        named(expanded, encode(item));
+                    } else {
+                        expanded.push_str(&encode(item));
+                    }
+                }
+            } else {
+                let list = items
+                    .iter()
+                    .map(|item| encode(item))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                if operator.named {
+                    named(expanded, list);
+                } else {
+                    expanded.push_str(&list);
+                }
+            }
+            continue;
+        }
+
+        let item = &items[0];
+        let item = match prefix {
+            Some(length) => item.chars().take(length).collect(),
+            None => item.clone(),
+        };
+        if operator.named {
+            named(expanded, encode(&item));
+        } else {
+            expanded.push_str(&encode(&item));
+        }
+    }
+
+    Ok(())
+}
+
+/// Split `name`, `name:3` or `name*` into the name, prefix length and explode flag
+fn parse_varspec<'a>(
+    spec: &'a str,
+    expression: &str,
+) -> Result<(&'a str, Option<usize>, bool), AppError> {
+    let invalid = || AppError {
+        code: ErrorCode::Misconfigured,
+        message: format!("Invalid URL template expression: {{{}}}", expression),
+    };
+
+    let spec = spec.trim();
+    let (name, prefix, explode) = if let Some(name) = spec.strip_suffix('*') {
+        (name, None, true)
+    } else if let Some((name, length)) = spec.split_once(':') {
+        (name, Some(length.parse().map_err(|_| invalid())?), false)
+    } else {
+        (spec, None, false)
+    };
+
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '%'))
+    {
+        return Err(invalid());
+    }
+
+    Ok((name, prefix, explode))
+}
+
+/// A string, number or boolean as text; `None` for anything else
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Percent-encode everything but unreserved characters, and reserved
+/// characters and existing escapes when `allow_reserved` is set
+fn encode(value: &str, allow_reserved: bool) -> String {
+    if !allow_reserved {
+        return urlencoding::encode(value).into_owned();
+    }
+
+    let bytes = value.as_bytes();
+    let mut encoded = String::with_capacity(value.len());
+    for (index, &byte) in bytes.iter().enumerate() {
+        let is_escape = byte == b'%'
+            && bytes.get(index + 1).is_some_and(u8::is_ascii_hexdigit)
+            && bytes.get(index + 2).is_some_and(u8::is_ascii_hexdigit);
+
+        if is_escape || byte.is_ascii_alphanumeric() || b"-._~:/?#[]@!$&'()*+,;=".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn values() -> Value {
+        json!({
+            "var": "value",
+            "hello": "Hello World!",
+            "path": "/foo/bar",
+            "list": ["red", "green", "blue"],
+            "x": 1024,
+            "y": 768,
+            "empty": "",
+            "auth": { "subdomain": "acme" },
+        })
+    }
+
+    #[test]
+    fn expands_the_rfc_6570_examples() {
+        // RFC 6570, section 1.2, levels 1 to 3 and the prefix and list forms of level 4
+        let cases = [
+            ("{var}", "value"),
+            ("{hello}", "Hello%20World%21"),
+            ("{+hello}", "Hello%20World!"),
+            ("{+path}/here", "/foo/bar/here"),
+            ("here?ref={+path}", "here?ref=/foo/bar"),
+            ("map?{x,y}", "map?1024,768"),
+            ("{x,hello,y}", "1024,Hello%20World%21,768"),
+            ("{+x,hello,y}", "1024,Hello%20World!,768"),
+            ("{+path,x}/here", "/foo/bar,1024/here"),
+            ("{#x,hello,y}", "#1024,Hello%20World!,768"),
+            ("{#path,x}/here", "#/foo/bar,1024/here"),
+            ("X{.var}", "X.value"),
+            ("X{.x,y}", "X.1024.768"),
+            ("{/var}", "/value"),
+            ("{/var,x}/here", "/value/1024/here"),
+            ("{;x,y}", ";x=1024;y=768"),
+            ("{;x,y,empty}", ";x=1024;y=768;empty"),
+            ("{?x,y}", "?x=1024&y=768"),
+            ("{?x,y,empty}", "?x=1024&y=768&empty="),
+            ("?fixed=yes{&x}", "?fixed=yes&x=1024"),
+            ("{&x,y,empty}", "&x=1024&y=768&empty="),
+            ("{var:3}", "val"),
+            ("{+path:6}/here", "/foo/b/here"),
+            ("{list}", "red,green,blue"),
+            ("{/list*}", "/red/green/blue"),
+            ("{/list*,path:4}", "/red/green/blue/%2Ffoo"),
+            ("{?list*}", "?list=red&list=green&list=blue"),
+        ];
+        for (template, expected) in cases {
+            assert_eq!(
+                expand(template, &values()).unwrap(),
+                expected,
+                "{}",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn expands_dotted_paths_and_encodes_ids() {
+        let values = json!({ "auth": { "subdomain": "acme" }, "id": "a/b?c" });
+        assert_eq!(
+            expand("https://{auth.subdomain}.example.com/items/{id}", &values).unwrap(),
+            "https://acme.example.com/items/a%2Fb%3Fc"
+        );
+    }
+
+    #[test]
+    fn missing_variables_fail_outside_query_expressions() {
+        assert_eq!(
+            expand("/items{?page,limit}", &json!({ "page": 2 })).unwrap(),
+            "/items?page=2"
+        );
+
+        let error = expand("/items/{id}", &json!({})).unwrap_err();
+        assert!(matches!(error.code, ErrorCode::Misconfigured));
+
+        let error = expand("/items/{id", &json!({ "id": 1 })).unwrap_err();
+        assert!(error.message.starts_with("Unclosed expression"));
+    }
+
+    #[test]
+    fn resolves_the_rfc_3986_examples() {
+        // RFC 3986, sections 5.4.1 and 5.4.2
+        let cases = [
+            ("g:h", "g:h"),
+            ("g", "http://a/b/c/g"),
+            ("./g", "http://a/b/c/g"),
+            ("g/", "http://a/b/c/g/"),
+            ("/g", "http://a/g"),
+            ("//g", "http://g"),
+            ("?y", "http://a/b/c/d;p?y"),
+            ("g?y", "http://a/b/c/g?y"),
+            ("#s", "http://a/b/c/d;p?q#s"),
+            ("g?y#s", "http://a/b/c/g?y#s"),
+            (";x", "http://a/b/c/;x"),
+            ("", "http://a/b/c/d;p?q"),
+            (".", "http://a/b/c/"),
+            ("..", "http://a/b/"),
+            ("../g", "http://a/b/g"),
+            ("../..", "http://a/"),
+            ("../../g", "http://a/g"),
+            ("../../../g", "http://a/g"),
+            ("/./g", "http://a/g"),
+            ("/../g", "http://a/g"),
+            ("g.", "http://a/b/c/g."),
+            ("..g", "http://a/b/c/..g"),
+            ("./../g", "http://a/b/g"),
+            ("./g/.", "http://a/b/c/g/"),
+            ("g/../h", "http://a/b/c/h"),
+            ("g;x=1/../y", "http://a/b/c/y"),
+            ("g?y/./x", "http://a/b/c/g?y/./x"),
+            ("g#s/../x", "http://a/b/c/g#s/../x"),
+            ("http:g", "http:g"),
+        ];
+        for (reference, expected) in cases {
+            assert_eq!(
+                resolve("http://a/b/c/d;p?q", reference),
+                expected,
+                "{}",
+                reference
+            );
+        }
+
+        assert_eq!(
+            resolve("https://api.example.com", "items"),
+            "https://api.example.com/items"
+        );
+        assert_eq!(
+            resolve("https://api.example.com/", "ünï/x"),
+            "https://api.example.com/ünï/x"
+        );
+    }
+
+    #[test]
+    fn joins_base_urls_and_endpoints() {
+        let cases = [
+            (
+                "https://api.example.com/v2/",
+                "users",
+                "https://api.example.com/v2/users",
+            ),
+            (
+                "https://api.example.com/v2",
+                "/users",
+                "https://api.example.com/v2/users",
+            ),
+            ("https://api.example.com", "", "https://api.example.com"),
+            (
+                "https://api.example.com/search",
+                "?q=1",
+                "https://api.example.com/search?q=1",
+            ),
+            (
+                "https://api.example.com",
+                "https://cdn.example.com/a",
+                "https://cdn.example.com/a",
+            ),
+            ("", "/users", "/users"),
+        ];
+        for (base_url, endpoint, expected) in cases {
+            assert_eq!(join(base_url, endpoint), expected);
+        }
+
+        assert!(is_absolute("git+ssh://host/repo"));
+        assert!(!is_absolute("/redirect?to=https://example.com"));
+    }
+}
@@ -0,0 +1,93 @@
+//! The time budget of the current `execute` or `fetch-events` invocation
+//!
+//! The host discards results returned after 30 seconds. The entrypoints in
+//! `lib.rs` start the clock for the length of the invocation, `ApiClient`
+//! checks it before every request, and actions and triggers can ask how much
+//! time is left, e.g. to stop paging and return what they have.
+
+use crate::standout::app::types::{AppError, ErrorCode};
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+/// How long the host waits for `execute` and `fetch-events`
+pub const BUDGET: Duration = Duration::from_secs(30);
+
+/// Time kept back to build and return the response
+pub const RESERVE: Duration = Duration::from_secs(2);
+
+thread_local! {
+    // Per thread, so tests running in parallel under the harness keep their own
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Start the budget of a new invocation, which ends when the guard drops
+#[must_use = "the budget ends as soon as the guard is dropped"]
+pub fn start() -> Budget {
+    set(Some(Instant::now() + BUDGET));
+    Budget(())
+}
+
+/// Clears the deadline when the invocation returns
+pub struct Budget(());
+
+impl Drop for Budget {
+    fn drop(&mut self) {
+        set(None);
+    }
+}
+
+fn set(deadline: Option<Instant>) {
+    DEADLINE.with(|cell| cell.set(deadline));
+}
+
+/// Time left before the host gives up, or `None` outside an invocation
+#[allow(dead_code)]
+pub fn remaining() -> Option<Duration> {
+    DEADLINE
+        .with(Cell::get)
+        .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+}
+
+/// Time left for work such as requests, after the reserve
+#[allow(dead_code)]
+pub fn usable() -> Option<Duration> {
+    remaining().map(|remaining| remaining.saturating_sub(RESERVE))
+}
+
+/// Whether there is time to wait this long and still do something afterwards
+pub fn allows(wait: Duration) -> bool {
+    usable().is_none_or(|usable| usable > wait)
+}
+
+/// Fail with `ErrorCode::Timeout` once only the reserve is left
+pub fn check() -> Result<(), AppError> {
+    if allows(Duration::ZERO) {
+        return Ok(());
+    }
+
+    Err(AppError {
+        code: ErrorCode::Timeout,
+        message: format!(
+            "Less than {}s left of the {}s invocation budget",
+            RESERVE.as_secs(),
+            BUDGET.as_secs()
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_ends_with_the_invocation() {
+        assert_eq!(remaining(), None);
+
+        let budget = start();
+        assert!(remaining().is_some_and(|remaining| remaining > BUDGET - RESERVE));
+        drop(budget);
+
+        assert_eq!(remaining(), None);
+        assert!(check().is_ok());
+    }
+}
@@ -0,0 +1,183 @@
+//! Conversions into `AppError` so `?` works on common errors
+//!
+//! The actions and triggers `AppError` are aliases of `types::AppError`, so
+//! these conversions apply to every function that returns one. Use
+//! `Context::context` to say what was being done when an error happened:
+//!
+//! ```ignore
+//! use crate::error::Context;
+//!
+//! let invoice: Invoice = serde_json::from_str(&body).context("loading invoice")?;
+//! // MalformedResponse: "loading invoice: Invalid JSON: expected value at line 1 column 1"
+//! ```
+
+use crate::client::error_code_for_request_error;
+use crate::standout::app::{
+    file::FileError,
+    http::RequestError,
+    types::{AppError, ErrorCode},
+};
+use std::fmt::Display;
+
+/// Parsing failures, which come from what an API returned
+///
+/// `serde_json::Error` does not say whether it came from parsing or from
+/// serializing, so serializing our own data maps its error with
+/// `serialize_error` instead, and parsing the connection data, input or store
+/// with `misconfigured`.
+impl From<serde_json::Error> for AppError {
+    fn from(err: serde_json::Error) -> Self {
+        let code = match err.classify() {
+            serde_json::error::Category::Io => ErrorCode::InternalError,
+            _ => ErrorCode::MalformedResponse,
+        };
+
+        AppError {
+            code,
+            message: format!("Invalid JSON: {}", err),
+        }
+    }
+}
+
+/// The error for failing to serialize our own data, with what was being done
+///
+/// ```ignore
+/// serde_json::to_string(&output).map_err(serialize_error("Failed to serialize response"))?;
+/// // InternalError: "Failed to serialize response: ..."
+/// ```
+pub fn serialize_error(context: impl Display) -> impl FnOnce(serde_json::Error) -> AppError {
+    move |err| AppError {
+        code: ErrorCode::InternalError,
+        message: format!("{}: {}", context, err),
+    }
+}
+
+/// The error for failing to parse what the platform passed in, with what was being done
+///
+/// ```ignore
+/// serde_json::from_str(&context.serialized_input).map_err(misconfigured("parsing input"))?;
+/// // Misconfigured: "parsing input: ..."
+/// ```
+#[allow(dead_code)]
+pub fn misconfigured(context: impl Display) -> impl FnOnce(serde_json::Error) -> AppError {
+    move |err| AppError {
+        code: ErrorCode::Misconfigured,
+        message: format!("{}: {}", context, err),
+    }
+}
+
+impl From<chrono::ParseError> for AppError {
+    fn from(err: chrono::ParseError) -> Self {
+        AppError {
+            code: ErrorCode::MalformedResponse,
+            message: format!("Invalid date: {}", err),
+        }
+    }
+}
+
+impl From<std::str::Utf8Error> for AppError {
+    fn from(err: std::str::Utf8Error) -> Self {
+        AppError {
+            code: ErrorCode::MalformedResponse,
+            message: format!("Invalid UTF-8: {}", err),
+        }
+    }
+}
+
+impl From<std::string::FromUtf8Error> for AppError {
+    fn from(err: std::string::FromUtf8Error) -> Self {
+        err.utf8_error().into()
+    }
+}
+
+impl From<RequestError> for AppError {
+    fn from(err: RequestError) -> Self {
+        let RequestError::Other(message) = err;
+
+        AppError {
+            code: error_code_for_request_error(&message),
+            message: format!("Request failed: {}", message),
+        }
+    }
+}
+
+impl From<FileError> for AppError {
+    fn from(err: FileError) -> Self {
+        let (code, message) = match err {
+            FileError::FetchFailed(message) => (ErrorCode::Unavailable, message),
+            FileError::InvalidInput(message) => (ErrorCode::Other, message),
+            FileError::Timeout(message) => (ErrorCode::Timeout, message),
+            FileError::Other(message) => (ErrorCode::Other, message),
+        };
+
+        AppError {
+            code,
+            message: format!("File error: {}", message),
+        }
+    }
+}
+
+/// Adds what was being done to the message of an error, keeping its code
+#[allow(dead_code)]
+pub trait Context<T> {
+    /// Prefix the error message with `context`
+    fn context(self, context: impl Display) -> Result<T, AppError>;
+
+    /// Prefix the error message with a lazily built context
+    fn with_context<C: Display>(self, context: impl FnOnce() -> C) -> Result<T, AppError>;
+}
+
+impl<T, E: Into<AppError>> Context<T> for Result<T, E> {
+    fn context(self, context: impl Display) -> Result<T, AppError> {
+        self.with_context(|| context)
+    }
+
+    fn with_context<C: Display>(self, context: impl FnOnce() -> C) -> Result<T, AppError> {
+        self.map_err(|err| {
+            let err = err.into();
+            AppError {
+                code: err.code,
+                message: format!("{}: {}", context(), err.message),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn parse_errors_blame_the_response() {
+        let err: AppError = serde_json::from_str::<u32>("\"one\"").unwrap_err().into();
+        assert!(matches!(err.code, ErrorCode::MalformedResponse));
+
+        let err = serde_json::from_str::<u32>("{")
+            .context("loading invoice")
+            .unwrap_err();
+        assert!(matches!(err.code, ErrorCode::MalformedResponse));
+        assert!(err.message.starts_with("loading invoice: Invalid JSON: "));
+    }
+
+    #[test]
+    fn serialize_errors_are_internal() {
+        // JSON object keys must be strings
+        let map = BTreeMap::from([((1, 2), "value")]);
+        let err = serde_json::to_string(&map)
+            .map_err(serialize_error("Failed to serialize response"))
+            .unwrap_err();
+        assert!(matches!(err.code, ErrorCode::InternalError));
+        assert!(err.message.starts_with("Failed to serialize response: "));
+    }
+
+    #[test]
+    fn unparsable_context_data_is_misconfigured() {
+        let err = serde_json::from_str::<Value>("{")
+            .map_err(misconfigured("parsing input"))
+            .unwrap_err();
+        assert!(matches!(err.code, ErrorCode::Misconfigured));
+        assert!(err.message.starts_with("parsing input: "));
+    }
+}
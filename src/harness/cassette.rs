@@ -0,0 +1,591 @@
+//! Record/replay of HTTP exchanges in JSON cassettes
+//!
+//! A cassette sits under the `http` stand-in: requests that no mock answers
+//! are replayed from the cassette, or sent to the real server and recorded
+//! when the cassette is recording. Recording needs the `cassette-record`
+//! feature, which adds an HTTP client for the host.
+//!
+//! Recordings are scrubbed with the deny-list of the HTTP trace, so
+//! credentials in headers, query parameters and JSON or form bodies (API
+//! keys, OAuth secrets and tokens) never reach the cassette file.
+
+use super::http::{ReceivedRequest, RequestError, Response, path_of, query_params};
+use crate::client::Redactor;
+use crate::wire::method_name;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+
+/// `once`, `replay` or `record`; overrides the mode of every cassette
+pub const MODE_VAR: &str = "STANDOUT_CASSETTE_MODE";
+
+/// When a cassette talks to the real server
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Record if the cassette file does not exist yet, otherwise replay
+    Once,
+    /// Only replay; requests without a recorded interaction fail
+    Replay,
+    /// Send every request to the server and overwrite the cassette
+    Record,
+}
+
+/// The parts of a request compared with recorded requests
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatchOn {
+    Method,
+    /// The URL path, ignoring scheme and host
+    Path,
+    /// The query parameters, in any order
+    Query,
+    /// The body; JSON bodies are compared as values
+    Body,
+}
+
+/// A cassette file and how requests are matched against it
+///
+/// `scrub_header`, `scrub_query_param` and `scrub_field` add to one deny-list,
+/// as the HTTP trace does, so a name added with any of them is scrubbed
+/// wherever it appears: in headers, query parameters and body fields alike.
+///
+/// ```ignore
+/// let _cassette = Cassette::new("tests/cassettes/create_contact.json")
+///     .match_on(&[MatchOn::Method, MatchOn::Path, MatchOn::Body])
+///     .scrub_header("X-Shop-Token")
+///     .insert()?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct Cassette {
+    path: PathBuf,
+    mode: Mode,
+    match_on: Vec<MatchOn>,
+    redactor: Redactor,
+}
+
+impl Cassette {
+    /// A cassette stored at `path`, relative to the crate root under `cargo test`
+    ///
+    /// Matches on method, path and query, in `Once` mode unless
+    /// `STANDOUT_CASSETTE_MODE` says otherwise.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Cassette {
+            path: path.into(),
+            mode: Mode::Once,
+            match_on: vec![MatchOn::Method, MatchOn::Path, MatchOn::Query],
+            redactor: Redactor::default(),
+        }
+    }
+
+    /// Set the mode, unless `STANDOUT_CASSETTE_MODE` is set
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Replace the parts of a request that must match a recorded request
+    pub fn match_on(mut self, match_on: &[MatchOn]) -> Self {
+        self.match_on = match_on.to_vec();
+        self
+    }
+
+    /// Also scrub this header from recorded requests and responses
+    ///
+    /// Adds to the shared deny-list, so query parameters and fields with
+    /// this name are scrubbed too.
+    pub fn scrub_header(self, name: impl AsRef<str>) -> Self {
+        self.scrub(name)
+    }
+
+    /// Also scrub this query parameter from recorded URLs; requests are
+    /// matched as if theirs were scrubbed too
+    ///
+    /// Adds to the shared deny-list, like `scrub_header`.
+    pub fn scrub_query_param(self, name: impl AsRef<str>) -> Self {
+        self.scrub(name)
+    }
+
+    /// Also scrub this JSON or form field from recorded bodies; request
+    /// bodies are matched as if theirs were scrubbed too
+    ///
+    /// Adds to the shared deny-list, like `scrub_header`.
+    pub fn scrub_field(self, name: impl AsRef<str>) -> Self {
+        self.scrub(name)
+    }
+
+    fn scrub(mut self, name: impl AsRef<str>) -> Self {
+        self.redactor = self.redactor.redact([name]);
+        self
+    }
+
+    /// Put the cassette in use on this thread until the guard is dropped
+    pub fn insert(self) -> Result<CassetteGuard, String> {
+        let mode = match std::env::var(MODE_VAR) {
+            Ok(mode) => parse_mode(&mode)?,
+            Err(_) => self.mode,
+        };
+        let recording = match mode {
+            Mode::Record => true,
+            Mode::Once => !self.path.exists(),
+            Mode::Replay => false,
+        };
+
+        let interactions = if recording {
+            Vec::new()
+        } else {
+            load(&self.path)?
+        };
+
+        ACTIVE.with(|active| {
+            *active.borrow_mut() = Some(Active {
+                cassette: self,
+                recording,
+                interactions,
+                used: Vec::new(),
+            })
+        });
+
+        Ok(CassetteGuard { _private: () })
+    }
+
+    fn scrub_url(&self, url: &str) -> String {
+        self.redactor.url(url)
+    }
+
+    fn scrub_headers(&self, headers: &[(String, String)]) -> Vec<(String, String)> {
+        self.redactor.headers(headers, &[])
+    }
+
+    /// Text bodies with denied JSON or form fields scrubbed; binary as is
+    fn scrub_body(&self, bytes: &[u8], headers: &[(String, String)]) -> RecordedBody {
+        let Ok(text) = std::str::from_utf8(bytes) else {
+            return RecordedBody::from_bytes(bytes);
+        };
+        let content_type = headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("Content-Type"))
+            .map(|(_, value)| value.as_str())
+            .unwrap_or_default();
+
+        RecordedBody::Text(self.redactor.text(text, content_type))
+    }
+
+    fn matches(&self, recorded: &RecordedRequest, request: &ReceivedRequest) -> bool {
+        let url = self.scrub_url(&request.url);
+
+        self.match_on.iter().all(|part| match part {
+            MatchOn::Method => recorded.method == method_name(request.method),
+            MatchOn::Path => path_of(without_query(&recorded.url)) == path_of(without_query(&url)),
+            MatchOn::Query => {
+                let mut recorded = query_params(&recorded.url);
+                let mut received = query_params(&url);
+                recorded.sort();
+                received.sort();
+                recorded == received
+            }
+            MatchOn::Body => {
+                let received = self.scrub_body(&request.body, &request.headers).to_bytes();
+                match (
+                    serde_json::from_slice::<Value>(&recorded.body.to_bytes()),
+                    serde_json::from_slice::<Value>(&received),
+                ) {
+                    (Ok(recorded), Ok(received)) => recorded == received,
+                    _ => recorded.body.to_bytes() == received,
+                }
+            }
+        })
+    }
+}
+
+/// Ejects the cassette when dropped, writing it first if it was recording
+#[must_use = "the cassette is ejected when the guard is dropped"]
+pub struct CassetteGuard {
+    _private: (),
+}
+
+impl CassetteGuard {
+    /// Eject the cassette, returning any error writing it
+    pub fn eject(self) -> Result<(), String> {
+        let result = eject();
+        std::mem::forget(self);
+        result
+    }
+}
+
+impl Drop for CassetteGuard {
+    fn drop(&mut self) {
+        if let Err(err) = eject()
+            && !std::thread::panicking()
+        {
+            panic!("{}", err);
+        }
+    }
+}
+
+struct Active {
+    cassette: Cassette,
+    recording: bool,
+    interactions: Vec<Interaction>,
+    used: Vec<usize>,
+}
+
+thread_local! {
+    static ACTIVE: RefCell<Option<Active>> = const { RefCell::new(None) };
+}
+
+/// Answer a request from the cassette in use, if any
+pub(super) fn play(request: &ReceivedRequest) -> Option<Result<Response, RequestError>> {
+    ACTIVE.with(|active| {
+        let mut active = active.borrow_mut();
+        let active = active.as_mut()?;
+
+        if active.recording {
+            let reply = send(request);
+            if let Ok(response) = &reply {
+                let cassette = &active.cassette;
+                active.interactions.push(Interaction {
+                    request: RecordedRequest {
+                        method: method_name(request.method).to_string(),
+                        url: cassette.scrub_url(&request.url),
+                        headers: cassette.scrub_headers(&request.headers),
+                        body: cassette.scrub_body(&request.body, &request.headers),
+                    },
+                    response: RecordedResponse {
+                        status: response.status,
+                        headers: cassette.scrub_headers(&response.headers),
+                        body: match &response.body_bytes {
+                            Some(bytes) => RecordedBody::from_bytes(bytes),
+                            None => {
+                                cassette.scrub_body(response.body.as_bytes(), &response.headers)
+                            }
+                        },
+                    },
+                });
+            }
+            return Some(reply);
+        }
+
+        // Interactions are replayed in order, each once
+        let index = (0..active.interactions.len()).find(|index| {
+            !active.used.contains(index)
+                && active
+                    .cassette
+                    .matches(&active.interactions[*index].request, request)
+        });
+        let Some(index) = index else {
+            return Some(Err(RequestError::Other(format!(
+                "No interaction in cassette {} matches {:?} {}",
+                active.cassette.path.display(),
+                request.method,
+                request.url
+            ))));
+        };
+        active.used.push(index);
+
+        let recorded = &active.interactions[index].response;
+        let bytes = recorded.body.to_bytes();
+        let body_bytes = match &recorded.body {
+            RecordedBody::Base64 { .. } => Some(bytes.clone()),
+            RecordedBody::Text(_) => None,
+        };
+        Some(Ok(Response {
+            status: recorded.status,
+            headers: recorded.headers.clone(),
+            body: String::from_utf8_lossy(&bytes).into_owned(),
+            body_bytes,
+        }))
+    })
+}
+
+fn eject() -> Result<(), String> {
+    let Some(active) = ACTIVE.with(|active| active.borrow_mut().take()) else {
+        return Ok(());
+    };
+    if !active.recording {
+        return Ok(());
+    }
+
+    let path = &active.cassette.path;
+    let file = CassetteFile {
+        interactions: active.interactions,
+    };
+    let json = serde_json::to_string_pretty(&file)
+        .map_err(|e| format!("Failed to serialize cassette: {}", e))?;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    std::fs::write(path, json + "\n")
+        .map_err(|e| format!("Failed to write cassette {}: {}", path.display(), e))
+}
+
+fn load(path: &Path) -> Result<Vec<Interaction>, String> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read cassette {}: {}", path.display(), e))?;
+    let file: CassetteFile = serde_json::from_str(&json)
+        .map_err(|e| format!("Invalid cassette {}: {}", path.display(), e))?;
+    Ok(file.interactions)
+}
+
+fn parse_mode(mode: &str) -> Result<Mode, String> {
+    match mode.to_ascii_lowercase().as_str() {
+        "once" => Ok(Mode::Once),
+        "replay" => Ok(Mode::Replay),
+        "record" => Ok(Mode::Record),
+        _ => Err(format!(
+            "{} must be once, replay or record, got {}",
+            MODE_VAR, mode
+        )),
+    }
+}
+
+/// Send the request to the real server
+#[cfg(feature = "cassette-record")]
+fn send(request: &ReceivedRequest) -> Result<Response, RequestError> {
+    crate::wire::send(
+        &crate::wire::agent(None),
+        request.method,
+        &request.url,
+        &request.headers,
+        request.body.clone(),
+    )
+    .map_err(|e| RequestError::Other(e.to_string()))
+}
+
+#[cfg(not(feature = "cassette-record"))]
+fn send(request: &ReceivedRequest) -> Result<Response, RequestError> {
+    Err(RequestError::Other(format!(
+        "Cannot record {:?} {}: enable the cassette-record feature",
+        request.method, request.url
+    )))
+}
+
+fn without_query(url: &str) -> &str {
+    url.split('?').next().unwrap_or_default()
+}
+
+#[derive(Serialize, Deserialize)]
+struct CassetteFile {
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct RecordedRequest {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: RecordedBody,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: RecordedBody,
+}
+
+/// Text bodies are stored as is, anything else as base64
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum RecordedBody {
+    Text(String),
+    Base64 { base64: String },
+}
+
+impl RecordedBody {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => RecordedBody::Text(text.to_string()),
+            Err(_) => RecordedBody::Base64 {
+                base64: crate::base64::encode(bytes),
+            },
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            RecordedBody::Text(text) => text.as_bytes().to_vec(),
+            RecordedBody::Base64 { base64 } => crate::base64::decode(base64).unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::ApiClient;
+    use crate::harness::http::Method;
+    use serde_json::json;
+
+    fn headers(content_type: &str) -> Vec<(String, String)> {
+        vec![("Content-Type".to_string(), content_type.to_string())]
+    }
+
+    fn text(body: RecordedBody) -> String {
+        String::from_utf8(body.to_bytes()).unwrap()
+    }
+
+    #[test]
+    fn credentials_are_scrubbed_by_default() {
+        let cassette = Cassette::new("unused.json");
+
+        let form = cassette.scrub_body(
+            b"grant_type=refresh_token&refresh_token=r1&client_id=app&client_secret=s3",
+            &headers("application/x-www-form-urlencoded"),
+        );
+        assert_eq!(
+            text(form),
+            "grant_type=refresh_token&refresh_token=[REDACTED]&client_id=app&client_secret=[REDACTED]"
+        );
+
+        let json = cassette.scrub_body(
+            br#"{"access_token":"a1","expires_in":3600}"#,
+            &headers("application/json"),
+        );
+        assert_eq!(
+            text(json),
+            r#"{"access_token":"[REDACTED]","expires_in":3600}"#
+        );
+
+        assert_eq!(
+            cassette.scrub_url("https://api.example.com/v1/items?api_key=k1&page=2"),
+            "https://api.example.com/v1/items?api_key=[REDACTED]&page=2"
+        );
+
+        let scrubbed = cassette.scrub_headers(&[
+            ("Authorization".to_string(), "Bearer a1".to_string()),
+            ("X-Api-Key".to_string(), "k1".to_string()),
+            ("Accept".to_string(), "application/json".to_string()),
+        ]);
+        let values: Vec<_> = scrubbed.iter().map(|(_, value)| value.as_str()).collect();
+        assert_eq!(values, ["[REDACTED]", "[REDACTED]", "application/json"]);
+    }
+
+    #[test]
+    fn bodies_without_credentials_are_kept_as_sent() {
+        let cassette = Cassette::new("unused.json").scrub_field("email");
+
+        let body = br#"{"name":"Ada",  "tags":[1,2]}"#;
+        assert_eq!(
+            text(cassette.scrub_body(body, &headers("application/json"))),
+            r#"{"name":"Ada",  "tags":[1,2]}"#
+        );
+        assert_eq!(
+            text(cassette.scrub_body(br#"{"email":"ada@example.com"}"#, &[])),
+            r#"{"email":"[REDACTED]"}"#
+        );
+    }
+
+    #[test]
+    fn requests_match_scrubbed_recordings() {
+        let cassette = Cassette::new("unused.json").match_on(&[
+            MatchOn::Method,
+            MatchOn::Query,
+            MatchOn::Body,
+        ]);
+        let recorded = RecordedRequest {
+            method: "POST".to_string(),
+            url: "https://auth.example.com/token?api_key=[REDACTED]".to_string(),
+            headers: Vec::new(),
+            body: RecordedBody::Text("client_id=app&client_secret=[REDACTED]".to_string()),
+        };
+        let request = ReceivedRequest {
+            method: Method::Post,
+            url: "https://auth.example.com/token?api_key=k1".to_string(),
+            headers: headers("application/x-www-form-urlencoded"),
+            body: b"client_id=app&client_secret=s3".to_vec(),
+        };
+        assert!(cassette.matches(&recorded, &request));
+
+        let other = ReceivedRequest {
+            body: b"client_id=other&client_secret=s3".to_vec(),
+            ..request
+        };
+        assert!(!cassette.matches(&recorded, &other));
+    }
+
+    fn interaction(method: &str, url: &str, body: &str, status: u16, reply: &str) -> Interaction {
+        Interaction {
+            request: RecordedRequest {
+                method: method.to_string(),
+                url: url.to_string(),
+                headers: headers("application/json"),
+                body: RecordedBody::Text(body.to_string()),
+            },
+            response: RecordedResponse {
+                status,
+                headers: headers("application/json"),
+                body: RecordedBody::Text(reply.to_string()),
+            },
+        }
+    }
+
+    #[test]
+    fn ejected_recordings_are_loaded_and_replayed_in_order() {
+        let dir = std::env::temp_dir().join(format!("cassettes-{}", std::process::id()));
+        let path = dir.join("contacts.json");
+        let _ = std::fs::remove_file(&path);
+
+        // Stand in for a recording session, which needs a real server
+        ACTIVE.with(|active| {
+            *active.borrow_mut() = Some(Active {
+                cassette: Cassette::new(&path),
+                recording: true,
+                interactions: vec![
+                    interaction(
+                        "POST",
+                        "https://api.example.com/contacts",
+                        r#"{"email":"ada@example.com","name":"Ada"}"#,
+                        201,
+                        r#"{"id":1}"#,
+                    ),
+                    interaction("GET", "https://api.example.com/contacts", "", 200, "[1]"),
+                    interaction("GET", "https://api.example.com/contacts", "", 200, "[1,2]"),
+                ],
+                used: Vec::new(),
+            })
+        });
+        eject().unwrap();
+        assert_eq!(load(&path).unwrap().len(), 3);
+
+        crate::harness::reset();
+        let guard = Cassette::new(&path)
+            .mode(Mode::Replay)
+            .match_on(&[MatchOn::Method, MatchOn::Path, MatchOn::Body])
+            .insert()
+            .unwrap();
+        let client = ApiClient::new(&json!({
+            "base_url": "https://api.example.com",
+            "headers": {},
+        }))
+        .unwrap();
+
+        // JSON bodies match as values, whatever the key order
+        let created = client
+            .post(
+                "/contacts",
+                &json!({ "name": "Ada", "email": "ada@example.com" }),
+            )
+            .unwrap();
+        assert_eq!(created, json!({ "id": 1 }));
+        assert!(client.post("/contacts", &json!({ "name": "Bob" })).is_err());
+
+        // Each interaction is used once, in the order it was recorded
+        assert_eq!(client.get("/contacts").unwrap(), json!([1]));
+        assert_eq!(client.get("/contacts").unwrap(), json!([1, 2]));
+        let err = client.get("/contacts").unwrap_err();
+        assert!(err.message.contains("No interaction in cassette"));
+
+        // Replaying leaves the file as it was
+        guard.eject().unwrap();
+        assert_eq!(load(&path).unwrap().len(), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,32 @@
+//! Stand-in for the `environment` import, backed by per-thread variables
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+thread_local! {
+    static VARS: RefCell<BTreeMap<String, String>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/// Get all environment variables
+pub fn env_vars() -> Vec<(String, String)> {
+    VARS.with(|vars| {
+        vars.borrow()
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    })
+}
+
+/// Get a specific environment variable by name
+pub fn env_var(name: &str) -> Option<String> {
+    VARS.with(|vars| vars.borrow().get(name).cloned())
+}
+
+/// Set a variable seen by `env_var` on this thread
+pub fn set_env_var(name: impl Into<String>, value: impl Into<String>) {
+    VARS.with(|vars| vars.borrow_mut().insert(name.into(), value.into()));
+}
+
+pub(super) fn clear() {
+    VARS.with(|vars| vars.borrow_mut().clear());
+}
@@ -0,0 +1,52 @@
+//! Stand-in for the `file` import
+//!
+//! URLs are fetched through the mocked `http` import, data URIs and base64
+//! are decoded in process.
+
+use super::http::{Method, RequestBuilder};
+
+/// HTTP headers for file requests (same as http interface)
+pub type Headers = Vec<(String, String)>;
+
+/// Normalized file data
+#[derive(Clone, Debug)]
+pub struct FileData {
+    /// Base64-encoded file content
+    pub base64: String,
+    /// MIME type (e.g., "application/pdf")
+    pub content_type: String,
+    /// Filename
+    pub filename: String,
+}
+
+#[derive(Clone, Debug)]
+pub enum FileError {
+    FetchFailed(String),
+    InvalidInput(String),
+    Timeout(String),
+    Other(String),
+}
+
+impl std::fmt::Display for FileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for FileError {}
+
+/// Normalize a URL, data URI or base64 string to FileData
+pub fn normalize(
+    source: &str,
+    headers: Option<&[(String, String)]>,
+    filename: Option<&str>,
+) -> Result<FileData, FileError> {
+    crate::wire::normalize_file(source, filename.map(str::to_string), |url| {
+        RequestBuilder::new()
+            .method(Method::Get)
+            .url(url)
+            .headers(headers.unwrap_or_default())
+            .send()
+            .map_err(|err| FileError::FetchFailed(err.to_string()))
+    })
+}
@@ -0,0 +1,39 @@
+//! An action and a trigger shaped like generated executors
+//!
+//! The routing templates route `harness_create_contact` and
+//! `harness_updated_orders` here under `cfg(test)`, so the harness tests
+//! exercise the generated routing and the exported entrypoints.
+
+use crate::client::ApiClient;
+use crate::error::misconfigured;
+use crate::standout::app::http::Method;
+use crate::standout::app::types::{ActionContext, AppError, TriggerContext, TriggerResponse};
+use crate::triggers::polling::Polling;
+use chrono::TimeDelta;
+use serde_json::Value;
+
+fn client(serialized_data: &str) -> Result<ApiClient, AppError> {
+    let connection_data: Value =
+        serde_json::from_str(serialized_data).map_err(misconfigured("parsing connection data"))?;
+    ApiClient::new(&connection_data)
+}
+
+/// POSTs the input to `/contacts`
+pub(crate) fn create_contact(context: ActionContext) -> Result<Value, AppError> {
+    let input: Value =
+        serde_json::from_str(&context.serialized_input).map_err(misconfigured("parsing input"))?;
+
+    client(&context.connection.serialized_data)?.post("/contacts", &input)
+}
+
+/// Polls `/orders` for orders updated since the last run
+pub(crate) fn updated_orders(context: TriggerContext) -> Result<TriggerResponse, AppError> {
+    let client = client(&context.connection.serialized_data)?;
+    let polling = Polling::new("updated_at").overlap(TimeDelta::minutes(1));
+
+    let mut request = client.request(Method::Get, "/orders");
+    if let Some(since) = polling.since(&context.store)? {
+        request = request.query("updated_since", since.to_rfc3339());
+    }
+    polling.respond(&context.store, request.send_as()?)
+}
@@ -0,0 +1,379 @@
+//! Stand-in for the `http` import that answers from registered mocks
+//!
+//! Mocks and received requests are kept per thread, so tests running in
+//! parallel do not see each other's mocks.
+
+use serde_json::Value;
+use std::cell::RefCell;
+
+pub type Headers = Vec<(String, String)>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+    Put,
+    Delete,
+    Patch,
+    Options,
+    Head,
+}
+
+#[derive(Clone, Debug)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Headers,
+    pub body: String,
+    /// Raw response payload for binary responses.
+    pub body_bytes: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: Method,
+    pub url: String,
+    pub headers: Headers,
+    pub body: String,
+}
+
+#[derive(Clone, Debug)]
+pub enum RequestError {
+    Other(String),
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for RequestError {}
+
+/// Builds a request the same way as the host's `request-builder` resource
+#[derive(Clone, Debug)]
+pub struct RequestBuilder {
+    method: Method,
+    url: String,
+    headers: Headers,
+    body: Vec<u8>,
+}
+
+impl Default for RequestBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestBuilder {
+    pub fn new() -> Self {
+        RequestBuilder {
+            method: Method::Get,
+            url: String::new(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn method(&self, method: Method) -> RequestBuilder {
+        RequestBuilder {
+            method,
+            ..self.clone()
+        }
+    }
+
+    pub fn url(&self, url: &str) -> RequestBuilder {
+        RequestBuilder {
+            url: url.to_string(),
+            ..self.clone()
+        }
+    }
+
+    pub fn header(&self, key: &str, value: &str) -> RequestBuilder {
+        let mut builder = self.clone();
+        builder.headers.push((key.to_string(), value.to_string()));
+        builder
+    }
+
+    pub fn headers(&self, headers: &[(String, String)]) -> RequestBuilder {
+        let mut builder = self.clone();
+        builder.headers.extend(headers.iter().cloned());
+        builder
+    }
+
+    pub fn body(&self, body: &str) -> RequestBuilder {
+        self.body_bytes(body.as_bytes())
+    }
+
+    pub fn body_bytes(&self, body: &[u8]) -> RequestBuilder {
+        RequestBuilder {
+            body: body.to_vec(),
+            ..self.clone()
+        }
+    }
+
+    pub fn object(&self) -> Request {
+        Request {
+            method: self.method,
+            url: self.url.clone(),
+            headers: self.headers.clone(),
+            body: String::from_utf8_lossy(&self.body).into_owned(),
+        }
+    }
+
+    /// Record the request and answer it from the most recently registered
+    /// mock that matches, or else from the cassette in use
+    pub fn send(&self) -> Result<Response, RequestError> {
+        let request = ReceivedRequest {
+            method: self.method,
+            url: self.url.clone(),
+            headers: self.headers.clone(),
+            body: self.body.clone(),
+        };
+        RECEIVED.with(|received| received.borrow_mut().push(request.clone()));
+
+        let reply = MOCKS.with(|mocks| {
+            let mut mocks = mocks.borrow_mut();
+            let mock = mocks
+                .iter_mut()
+                .rev()
+                .find(|mock| mock.remaining != Some(0) && mock.matches(&request))?;
+
+            if let Some(remaining) = &mut mock.remaining {
+                *remaining -= 1;
+            }
+            Some(mock.reply.clone())
+        });
+
+        reply
+            .or_else(|| super::cassette::play(&request))
+            .unwrap_or_else(|| {
+                Err(RequestError::Other(format!(
+                    "No mock matches {:?} {}",
+                    request.method, request.url
+                )))
+            })
+    }
+}
+
+/// A request the code under test sent
+#[derive(Clone, Debug)]
+pub struct ReceivedRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+impl ReceivedRequest {
+    /// The first value of the header, compared case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The first value of the query parameter, decoded
+    pub fn query(&self, name: &str) -> Option<String> {
+        query_params(&self.url)
+            .into_iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+    }
+
+    /// The body as text
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    /// The body parsed as JSON, `Value::Null` if it is not JSON
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).unwrap_or(Value::Null)
+    }
+}
+
+struct Mock {
+    method: Method,
+    url: String,
+    query: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+    body_contains: Option<String>,
+    remaining: Option<usize>,
+    reply: Result<Response, RequestError>,
+}
+
+impl Mock {
+    fn matches(&self, request: &ReceivedRequest) -> bool {
+        let request_url = request.url.split('?').next().unwrap_or_default();
+        let url_matches = if self.url.contains("://") {
+            request_url == self.url
+        } else {
+            let path = path_of(request_url);
+            path == self.url
+                || path
+                    .strip_suffix(self.url.as_str())
+                    .is_some_and(|prefix| self.url.starts_with('/') || prefix.ends_with('/'))
+        };
+
+        let request_query = query_params(&request.url);
+
+        self.method == request.method
+            && url_matches
+            && self.query.iter().all(|param| request_query.contains(param))
+            && self
+                .headers
+                .iter()
+                .all(|(key, value)| request.header(key) == Some(value.as_str()))
+            && self
+                .body_contains
+                .as_ref()
+                .is_none_or(|needle| request.text().contains(needle.as_str()))
+    }
+}
+
+thread_local! {
+    static MOCKS: RefCell<Vec<Mock>> = const { RefCell::new(Vec::new()) };
+    static RECEIVED: RefCell<Vec<ReceivedRequest>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Start a mock for requests with this method and URL
+///
+/// `url` is either absolute (`https://api.example.com/v1/users`) or a path
+/// matched against the end of the request path on any host, so `/users`
+/// matches `https://api.example.com/v1/users` but not `/v1/superusers`. Query parameters in
+/// `url` must all be present in the request. Later mocks take precedence,
+/// so a mock limited with `times` can shadow a general one.
+pub fn mock(method: Method, url: &str) -> MockBuilder {
+    let path = url.split('?').next().unwrap_or_default();
+
+    MockBuilder {
+        mock: Mock {
+            method,
+            url: path.to_string(),
+            query: query_params(url),
+            headers: Vec::new(),
+            body_contains: None,
+            remaining: None,
+            reply: Err(RequestError::Other("Mock has no response".to_string())),
+        },
+        response_headers: Vec::new(),
+    }
+}
+
+/// Conditions and the answer of a mock; registered by one of the `respond` methods
+pub struct MockBuilder {
+    mock: Mock,
+    response_headers: Headers,
+}
+
+impl MockBuilder {
+    /// Only match requests with this query parameter
+    pub fn with_query(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.mock.query.push((key.into(), value.into()));
+        self
+    }
+
+    /// Only match requests with this header value
+    pub fn with_header(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.mock.headers.push((key.into(), value.into()));
+        self
+    }
+
+    /// Only match requests whose body contains the text
+    pub fn with_body_containing(mut self, text: impl Into<String>) -> Self {
+        self.mock.body_contains = Some(text.into());
+        self
+    }
+
+    /// Answer only the next `times` matching requests
+    pub fn times(mut self, times: usize) -> Self {
+        self.mock.remaining = Some(times);
+        self
+    }
+
+    /// Add a header to the response
+    pub fn response_header(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.response_headers.push((key.into(), value.into()));
+        self
+    }
+
+    /// Answer with a text body
+    pub fn respond(self, status: u16, body: impl Into<String>) {
+        self.register(Ok(Response {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+            body_bytes: None,
+        }));
+    }
+
+    /// Answer with a JSON body and `Content-Type: application/json`
+    pub fn respond_json(self, status: u16, body: &Value) {
+        self.response_header("Content-Type", "application/json")
+            .respond(status, body.to_string());
+    }
+
+    /// Answer with a binary body
+    pub fn respond_bytes(self, status: u16, content_type: &str, body: Vec<u8>) {
+        self.response_header("Content-Type", content_type)
+            .register(Ok(Response {
+                status,
+                headers: Vec::new(),
+                body: String::new(),
+                body_bytes: Some(body),
+            }));
+    }
+
+    /// Fail the request without a response, like a connection error
+    pub fn fail(self, message: impl Into<String>) {
+        self.register(Err(RequestError::Other(message.into())));
+    }
+
+    fn register(mut self, reply: Result<Response, RequestError>) {
+        self.mock.reply = reply.map(|mut response| {
+            response.headers.splice(0..0, self.response_headers);
+            response
+        });
+        MOCKS.with(|mocks| mocks.borrow_mut().push(self.mock));
+    }
+}
+
+/// Every request sent on this thread since the last `reset`
+pub fn received_requests() -> Vec<ReceivedRequest> {
+    RECEIVED.with(|received| received.borrow().clone())
+}
+
+pub(super) fn clear() {
+    MOCKS.with(|mocks| mocks.borrow_mut().clear());
+    RECEIVED.with(|received| received.borrow_mut().clear());
+}
+
+/// The path of an absolute URL, or the URL itself if it is a path
+pub(super) fn path_of(url: &str) -> &str {
+    match url.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("/", |index| &rest[index..]),
+        None => url,
+    }
+}
+
+/// The decoded query parameters of a URL
+pub(super) fn query_params(url: &str) -> Vec<(String, String)> {
+    let Some((_, query)) = url.split_once('?') else {
+        return Vec::new();
+    };
+
+    let decode = |component: &str| {
+        urlencoding::decode(&component.replace('+', " "))
+            .map(|decoded| decoded.into_owned())
+            .unwrap_or_else(|_| component.to_string())
+    };
+
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(key), decode(value))
+        })
+        .collect()
+}
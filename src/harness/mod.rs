@@ -0,0 +1,184 @@
+//! In-process stand-ins for the `http`, `environment` and `file` imports
+//!
+//! Compiled for `cargo test` and with the `test-harness` feature. The crate
+//! then builds for the host target and `crate::standout::app::{http,
+//! environment, file}` point at these modules, so actions and triggers can
+//! be run from plain Rust tests without wasm, WireMock or network access.
+//! `execute` and `fetch_events` go through the same entrypoints as the host;
+//! `crate::execute_action_dynamically` and
+//! `crate::execute_trigger_fetch_events_dynamically` skip them:
+//!
+//! ```ignore
+//! use crate::harness::{self, http::Method};
+//!
+//! #[test]
+//! fn creates_a_contact() {
+//!     harness::reset();
+//!     harness::mock(Method::Post, "/contacts").respond_json(201, &json!({ "id": 42 }));
+//!
+//!     let context = harness::action_context(
+//!         "create_contact",
+//!         &json!({ "base_url": "https://api.example.com", "headers": {} }),
+//!         &json!({ "email": "jane@example.com" }),
+//!     );
+//!     let response = harness::execute(context).unwrap();
+//!     let output: Value = serde_json::from_str(&response.serialized_output).unwrap();
+//!
+//!     assert_eq!(output["id"], 42);
+//!     assert_eq!(harness::received_requests()[0].json()["email"], "jane@example.com");
+//! }
+//! ```
+
+pub mod cassette;
+pub mod environment;
+pub mod file;
+#[cfg(test)]
+pub(crate) mod fixtures;
+pub mod http;
+
+pub use cassette::{Cassette, CassetteGuard, MatchOn, Mode};
+pub use environment::set_env_var;
+pub use http::{MockBuilder, ReceivedRequest, mock, received_requests};
+
+use crate::App;
+use crate::exports::standout::app::actions::Guest as ActionsGuest;
+use crate::exports::standout::app::triggers::Guest as TriggersGuest;
+use crate::standout::app::types::{
+    ActionContext, ActionResponse, AppError, Connection, TriggerContext, TriggerResponse,
+};
+use serde_json::Value;
+
+/// Forget the mocks, received requests and environment variables of this thread
+pub fn reset() {
+    http::clear();
+    environment::clear();
+}
+
+/// A connection with the given connection data
+pub fn connection(connection_data: &Value) -> Connection {
+    Connection {
+        id: "test-connection".to_string(),
+        name: "Test connection".to_string(),
+        serialized_data: connection_data.to_string(),
+    }
+}
+
+/// The context `execute` receives for the action
+pub fn action_context(action_id: &str, connection_data: &Value, input: &Value) -> ActionContext {
+    ActionContext {
+        action_id: action_id.to_string(),
+        connection: connection(connection_data),
+        serialized_input: input.to_string(),
+        reference_object: None,
+    }
+}
+
+/// The context `fetch-events` receives for the trigger, with an empty store
+/// for the first run
+pub fn trigger_context(
+    trigger_id: &str,
+    connection_data: &Value,
+    input: &Value,
+    store: &str,
+) -> TriggerContext {
+    TriggerContext {
+        trigger_id: trigger_id.to_string(),
+        connection: connection(connection_data),
+        store: store.to_string(),
+        serialized_input: input.to_string(),
+    }
+}
+
+/// Run the action through the exported `execute`, as the host would
+pub fn execute(context: ActionContext) -> Result<ActionResponse, AppError> {
+    <App as ActionsGuest>::execute(context)
+}
+
+/// Run the trigger through the exported `fetch-events`, as the host would
+pub fn fetch_events(context: TriggerContext) -> Result<TriggerResponse, AppError> {
+    <App as TriggersGuest>::fetch_events(context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::Method;
+    use serde_json::json;
+
+    fn connection_data() -> Value {
+        json!({
+            "base_url": "https://api.example.com",
+            "auth": { "type": "bearer", "token": "secret" },
+        })
+    }
+
+    #[test]
+    fn runs_an_action_against_mocks() {
+        reset();
+        mock(Method::Post, "/contacts").respond_json(201, &json!({ "id": 42 }));
+
+        let context = action_context(
+            "harness_create_contact",
+            &connection_data(),
+            &json!({ "email": "jane@example.com" }),
+        );
+        let response = execute(context).unwrap();
+        let output: Value = serde_json::from_str(&response.serialized_output).unwrap();
+
+        assert_eq!(output["id"], 42);
+        let received = received_requests();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].header("Authorization"), Some("Bearer secret"));
+        assert_eq!(received[0].json()["email"], "jane@example.com");
+    }
+
+    #[test]
+    fn runs_a_trigger_fetch_across_invocations() {
+        reset();
+        let latest = json!({ "id": 2, "updated_at": "2024-05-01T10:04:00Z" });
+        mock(Method::Get, "/orders").respond_json(200, &json!([latest]));
+        mock(Method::Get, "/orders").times(1).respond_json(
+            200,
+            &json!([{ "id": 1, "updated_at": "2024-05-01T10:00:00Z" }, latest]),
+        );
+
+        let context = trigger_context("harness_updated_orders", &connection_data(), &json!({}), "");
+        let first = fetch_events(context).unwrap();
+        assert_eq!(first.events.len(), 2);
+
+        // The second run asks from the watermark and emits nothing already seen
+        let context = trigger_context(
+            "harness_updated_orders",
+            &connection_data(),
+            &json!({}),
+            &first.store,
+        );
+        let second = fetch_events(context).unwrap();
+        assert!(second.events.is_empty());
+
+        let received = received_requests();
+        assert_eq!(received[0].query("updated_since"), None);
+        assert_eq!(
+            received[1].query("updated_since").as_deref(),
+            Some("2024-05-01T10:03:00+00:00")
+        );
+    }
+
+    #[test]
+    fn entrypoints_report_unknown_actions_and_triggers() {
+        reset();
+
+        let error = execute(action_context("missing", &connection_data(), &json!({}))).unwrap_err();
+        assert_eq!(error.message, "Action 'missing' not implemented");
+
+        let error = fetch_events(trigger_context(
+            "missing",
+            &connection_data(),
+            &json!({}),
+            "",
+        ))
+        .unwrap_err();
+        assert_eq!(error.message, "Trigger 'missing' not implemented");
+        assert!(received_requests().is_empty());
+    }
+}
@@ -0,0 +1,14 @@
+//! Dotted paths into JSON values, as used by profiles, templates and triggers
+
+use serde_json::Value;
+
+/// The value at a dotted path such as `auth.subdomain`
+pub fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(value, |value, key| value.get(key))
+}
+
+/// The value at a dotted path, to change in place
+pub fn lookup_mut<'a>(value: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    path.split('.')
+        .try_fold(value, |value, key| value.get_mut(key))
+}
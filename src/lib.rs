@@ -1,8 +1,15 @@
 #![recursion_limit = "512"]
 
 mod actions;
+mod base64;
 mod client;
+mod deadline;
+mod error;
+#[cfg(any(test, feature = "test-harness"))]
+pub mod harness;
+mod json;
 mod triggers;
+mod wire;
 
 // Include the dynamically generated action routing
 include!("schemas/generated/action_routing.rs");
@@ -13,13 +20,42 @@ include!("schemas/generated/trigger_routing.rs");
 // Include the embedded schemas
 include!("schemas/generated/embedded_schemas.rs");
 
-use wit_bindgen::generate;
+// Include the embedded connection profile
+include!("schemas/generated/connection_profile.rs");
 
-generate!({
+#[cfg(not(any(test, feature = "test-harness")))]
+wit_bindgen::generate!({
     path: "wit/standout-app.wit",
     world: "bridge",
 });
 
+// With the test harness the imports are implemented in Rust by `harness`, and
+// `standout::app` is rebuilt so the rest of the crate uses the same paths
+#[cfg(any(test, feature = "test-harness"))]
+mod bindings {
+    wit_bindgen::generate!({
+        path: "wit/standout-app.wit",
+        world: "bridge",
+        with: {
+            "standout:app/http@4.1.0": crate::harness::http,
+            "standout:app/environment@4.1.0": crate::harness::environment,
+            "standout:app/file@4.1.0": crate::harness::file,
+        },
+    });
+}
+
+#[cfg(any(test, feature = "test-harness"))]
+pub mod standout {
+    pub mod app {
+        pub use crate::bindings::standout::app::types;
+        pub use crate::harness::{environment, file, http};
+    }
+}
+
+#[cfg(any(test, feature = "test-harness"))]
+pub(crate) use bindings::exports;
+
+use crate::error::serialize_error;
 use crate::exports::standout::app::actions::{AppError as ActionsAppError, Guest as ActionsGuest};
 use crate::exports::standout::app::triggers::{
     AppError as TriggersAppError, Guest as TriggersGuest,
@@ -36,41 +72,35 @@ impl TriggersGuest for App {
 
     fn input_schema(context: TriggerContext) -> Result<String, TriggersAppError> {
         // Call the executor's input_schema method dynamically
-        let schema_value = execute_trigger_input_schema_dynamically(&context.trigger_id, &context)
-            .map_err(|e| TriggersAppError {
-                code: e.code,
-                message: e.message,
-            })?;
-
-        serde_json::to_string_pretty(&schema_value).map_err(|e| TriggersAppError {
-            code: crate::standout::app::types::ErrorCode::Other,
-            message: format!("Failed to serialize schema: {}", e),
-        })
+        let schema_value = execute_trigger_input_schema_dynamically(&context.trigger_id, &context)?;
+
+        serde_json::to_string_pretty(&schema_value)
+            .map_err(serialize_error("Failed to serialize schema"))
     }
 
     fn output_schema(context: TriggerContext) -> Result<String, TriggersAppError> {
         // Call the executor's output_schema method dynamically
-        let schema_value = execute_trigger_output_schema_dynamically(&context.trigger_id, &context)
-            .map_err(|e| TriggersAppError {
-                code: e.code,
-                message: e.message,
-            })?;
-
-        serde_json::to_string_pretty(&schema_value).map_err(|e| TriggersAppError {
-            code: crate::standout::app::types::ErrorCode::Other,
-            message: format!("Failed to serialize schema: {}", e),
-        })
+        let schema_value =
+            execute_trigger_output_schema_dynamically(&context.trigger_id, &context)?;
+
+        serde_json::to_string_pretty(&schema_value)
+            .map_err(serialize_error("Failed to serialize schema"))
     }
 
     fn fetch_events(context: TriggerContext) -> Result<TriggerResponse, TriggersAppError> {
-        // Execute the appropriate trigger dynamically
+        let _budget = deadline::start();
+
+        // Execute the appropriate trigger dynamically, keeping events over the
+        // limit for the next invocation
         let trigger_id = context.trigger_id.clone();
-        execute_trigger_fetch_events_dynamically(&trigger_id, context).map_err(|e| {
-            TriggersAppError {
-                code: e.code,
-                message: e.message,
-            }
-        })
+        let response = triggers::overflow::fetch_events(context, |context| {
+            execute_trigger_fetch_events_dynamically(&trigger_id, context)
+        })?;
+
+        // The host ignores the whole response if the store is too large
+        triggers::store::check_size(&response.store)?;
+
+        Ok(response)
     }
 }
 
@@ -82,49 +112,34 @@ impl ActionsGuest for App {
 
     fn input_schema(context: ActionContext) -> Result<String, ActionsAppError> {
         // Call the executor's input_schema method dynamically
-        let schema_value = execute_action_input_schema_dynamically(&context.action_id, &context)
-            .map_err(|e| ActionsAppError {
-                code: e.code,
-                message: e.message,
-            })?;
-
-        serde_json::to_string_pretty(&schema_value).map_err(|e| ActionsAppError {
-            code: crate::standout::app::types::ErrorCode::Other,
-            message: format!("Failed to serialize schema: {}", e),
-        })
+        let schema_value = execute_action_input_schema_dynamically(&context.action_id, &context)?;
+
+        serde_json::to_string_pretty(&schema_value)
+            .map_err(serialize_error("Failed to serialize schema"))
     }
 
     fn output_schema(context: ActionContext) -> Result<String, ActionsAppError> {
         // Call the executor's output_schema method dynamically
-        let schema_value = execute_action_output_schema_dynamically(&context.action_id, &context)
-            .map_err(|e| ActionsAppError {
-            code: e.code,
-            message: e.message,
-        })?;
+        let schema_value = execute_action_output_schema_dynamically(&context.action_id, &context)?;
 
-        serde_json::to_string_pretty(&schema_value).map_err(|e| ActionsAppError {
-            code: crate::standout::app::types::ErrorCode::Other,
-            message: format!("Failed to serialize schema: {}", e),
-        })
+        serde_json::to_string_pretty(&schema_value)
+            .map_err(serialize_error("Failed to serialize schema"))
     }
 
     fn execute(context: ActionContext) -> Result<ActionResponse, ActionsAppError> {
+        let _budget = deadline::start();
+
         // Execute the appropriate action dynamically
         let action_id = context.action_id.clone();
-        let result =
-            execute_action_dynamically(&action_id, context).map_err(|e| ActionsAppError {
-                code: e.code,
-                message: e.message,
-            })?;
+        let result = execute_action_dynamically(&action_id, context)?;
 
         // Serialize the response
-        let serialized_output = serde_json::to_string(&result).map_err(|_e| ActionsAppError {
-            code: crate::standout::app::types::ErrorCode::Other,
-            message: "Failed to serialize response".to_string(),
-        })?;
+        let serialized_output = serde_json::to_string(&result)
+            .map_err(serialize_error("Failed to serialize response"))?;
 
         Ok(ActionResponse { serialized_output })
     }
 }
 
+#[cfg(not(any(test, feature = "test-harness")))]
 export!(App);
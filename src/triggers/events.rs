@@ -0,0 +1,254 @@
+use crate::error::serialize_error;
+use crate::json::lookup;
+use crate::standout::app::types::{AppError, ErrorCode, TriggerEvent};
+use serde::Serialize;
+use serde_json::Value;
+
+/// How the ID of a `TriggerEvent` is derived from an API item
+///
+/// The host ignores events whose ID it has seen before for the connection,
+/// so the strategy decides what counts as a new event:
+///
+/// - `EventId::field("id")`: once per item
+/// - `EventId::composite(["id", "updated_at"])`: once per update of an item
+/// - `EventId::content_hash()`: once per distinct content, for items without
+///   a usable ID
+///
+/// ```ignore
+/// let orders: Vec<Value> = client.get_as("/orders")?;
+/// let events = EventId::composite(["id", "updated_at"]).events(&orders)?;
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EventId {
+    /// The value at a dotted path
+    Field(String),
+    /// The values at several dotted paths, joined with `|`
+    Composite(Vec<String>),
+    /// FNV-1a hash of the item as canonical JSON
+    ContentHash,
+}
+
+impl EventId {
+    /// The value at a dotted path such as `id` or `data.order_id`
+    #[allow(dead_code)]
+    pub fn field(path: impl Into<String>) -> Self {
+        EventId::Field(path.into())
+    }
+
+    /// The values at several dotted paths, in order
+    #[allow(dead_code)]
+    pub fn composite<I, S>(paths: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        EventId::Composite(paths.into_iter().map(Into::into).collect())
+    }
+
+    /// A hash of the whole item, independent of key order
+    #[allow(dead_code)]
+    pub fn content_hash() -> Self {
+        EventId::ContentHash
+    }
+
+    /// The event ID of an item
+    pub fn id(&self, item: &Value) -> Result<String, AppError> {
+        match self {
+            EventId::Field(path) => field_value(item, path),
+            EventId::Composite(paths) => Ok(paths
+                .iter()
+                .map(|path| field_value(item, path).map(|value| escape(&value)))
+                .collect::<Result<Vec<_>, _>>()?
+                .join("|")),
+            EventId::ContentHash => Ok(format!("{:016x}", content_hash(item))),
+        }
+    }
+
+    /// The event for an item, which must serialize to a JSON object
+    pub fn event<T: Serialize + ?Sized>(&self, item: &T) -> Result<TriggerEvent, AppError> {
+        let item =
+            serde_json::to_value(item).map_err(serialize_error("Failed to serialize event"))?;
+        if !item.is_object() {
+            return Err(AppError {
+                code: ErrorCode::MalformedResponse,
+                message: format!("Trigger event data must be a JSON object, got {}", item),
+            });
+        }
+
+        Ok(TriggerEvent {
+            id: self.id(&item)?,
+            serialized_data: item.to_string(),
+        })
+    }
+
+    /// The events for a list of items, in order
+    #[allow(dead_code)]
+    pub fn events<T: Serialize>(&self, items: &[T]) -> Result<Vec<TriggerEvent>, AppError> {
+        items.iter().map(|item| self.event(item)).collect()
+    }
+}
+
+/// Stable 64-bit FNV-1a hash of the value as canonical JSON
+pub fn content_hash(value: &Value) -> u64 {
+    let mut canonical = String::new();
+    write_canonical(value, &mut canonical);
+    fnv1a(canonical.as_bytes())
+}
+
+/// 64-bit FNV-1a
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// JSON with object keys sorted and no whitespace
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(fields) => {
+            let mut keys = fields.keys().collect::<Vec<_>>();
+            keys.sort();
+
+            out.push('{');
+            for (index, key) in keys.into_iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(&fields[key], out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+/// The value at a path as ID text; objects and arrays as canonical JSON
+fn field_value(item: &Value, path: &str) -> Result<String, AppError> {
+    match lookup(item, path) {
+        Some(Value::String(value)) => Ok(value.clone()),
+        Some(Value::Number(value)) => Ok(value.to_string()),
+        Some(Value::Bool(value)) => Ok(value.to_string()),
+        Some(value @ (Value::Object(_) | Value::Array(_))) => {
+            let mut canonical = String::new();
+            write_canonical(value, &mut canonical);
+            Ok(canonical)
+        }
+        Some(Value::Null) | None => Err(AppError {
+            code: ErrorCode::MalformedResponse,
+            message: format!("Missing event ID field {}", path),
+        }),
+    }
+}
+
+/// Keep `|` unambiguous as the composite separator
+pub fn escape(value: &str) -> String {
+    value.replace('%', "%25").replace('|', "%7C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn field_ids_follow_dotted_paths() {
+        let item = json!({ "id": 7, "data": { "order_id": "A-1", "paid": true } });
+
+        assert_eq!(EventId::field("id").id(&item).unwrap(), "7");
+        assert_eq!(EventId::field("data.order_id").id(&item).unwrap(), "A-1");
+        assert_eq!(EventId::field("data.paid").id(&item).unwrap(), "true");
+        assert_eq!(
+            EventId::field("data").id(&item).unwrap(),
+            r#"{"order_id":"A-1","paid":true}"#
+        );
+
+        let err = EventId::field("data.missing").id(&item).unwrap_err();
+        assert!(matches!(err.code, ErrorCode::MalformedResponse));
+        assert_eq!(err.message, "Missing event ID field data.missing");
+        assert!(EventId::field("id").id(&json!({ "id": null })).is_err());
+    }
+
+    #[test]
+    fn composite_ids_escape_the_separator() {
+        let id = EventId::composite(["id", "updated_at"]);
+
+        assert_eq!(
+            id.id(&json!({ "id": 7, "updated_at": "2024-05-01" }))
+                .unwrap(),
+            "7|2024-05-01"
+        );
+        // Without escaping, both would be "a|b|c"
+        assert_eq!(
+            id.id(&json!({ "id": "a|b", "updated_at": "c" })).unwrap(),
+            "a%7Cb|c"
+        );
+        assert_eq!(
+            id.id(&json!({ "id": "a", "updated_at": "b|c" })).unwrap(),
+            "a|b%7Cc"
+        );
+        assert_eq!(
+            id.id(&json!({ "id": "50%", "updated_at": "x" })).unwrap(),
+            "50%25|x"
+        );
+        assert!(id.id(&json!({ "id": 7 })).is_err());
+    }
+
+    #[test]
+    fn content_hashes_ignore_key_order() {
+        let id = EventId::content_hash();
+        let a = id
+            .id(&json!({ "a": 1, "b": [1, { "c": 2, "d": 3 }] }))
+            .unwrap();
+        let b = id
+            .id(&json!({ "b": [1, { "d": 3, "c": 2 }], "a": 1 }))
+            .unwrap();
+
+        assert_eq!(a, b);
+        assert_eq!(a.len(), 16);
+        assert_ne!(
+            a,
+            id.id(&json!({ "a": 1, "b": [{ "c": 2, "d": 3 }, 1] }))
+                .unwrap()
+        );
+        assert_eq!(
+            EventId::content_hash().id(&json!({})).unwrap(),
+            format!("{:016x}", fnv1a(b"{}"))
+        );
+    }
+
+    #[test]
+    fn events_must_be_objects() {
+        let event = EventId::field("id")
+            .event(&json!({ "id": 1, "name": "Ada" }))
+            .unwrap();
+        assert_eq!(event.id, "1");
+        assert_eq!(event.serialized_data, r#"{"id":1,"name":"Ada"}"#);
+
+        for item in [json!([1, 2]), json!("id"), json!(1), Value::Null] {
+            let err = EventId::content_hash().event(&item).unwrap_err();
+            assert!(matches!(err.code, ErrorCode::MalformedResponse));
+            assert!(
+                err.message
+                    .starts_with("Trigger event data must be a JSON object")
+            );
+        }
+
+        let events = EventId::field("id")
+            .events(&[json!({ "id": 1 }), json!({ "id": 2 })])
+            .unwrap();
+        let ids: Vec<_> = events.iter().map(|event| event.id.as_str()).collect();
+        assert_eq!(ids, ["1", "2"]);
+    }
+}
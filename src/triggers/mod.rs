@@ -1,2 +1,11 @@
 // Auto-generated file - do not edit manually
 // Generated from existing trigger executors at compile time
+
+// Trigger utilities
+pub mod events;
+pub mod overflow;
+pub mod polling;
+pub mod snapshot;
+pub mod store;
+
+// Include generated trigger executors
@@ -0,0 +1,384 @@
+//! Keeps events over the host's 100-event limit for the next invocation
+//!
+//! The host ignores a response with more than 100 events. `fetch_events`
+//! returns the first 100 and keeps the rest in a reserved section of the
+//! store, `{"standout_overflow": {...}}`, holding the connector's own store.
+//! The next invocation emits the kept events first. If they do not fit in
+//! the store, the IDs of the emitted events are kept instead and the trigger
+//! runs again from its previous store, skipping those events. Once the IDs do
+//! not fit either, only their count is kept, which relies on the trigger
+//! returning its events in the same order when run from the same store.
+//!
+//! Should the previous store be too large to keep even with a count,
+//! `fetch_events` fails rather than lose the events over the limit.
+
+use super::store::MAX_STORE_BYTES;
+use crate::error::serialize_error;
+use crate::standout::app::types::{
+    AppError, ErrorCode, TriggerContext, TriggerEvent, TriggerResponse,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The most events the host accepts in one response
+pub const MAX_EVENTS: usize = 100;
+
+/// The store key of the reserved section
+pub const OVERFLOW_KEY: &str = "standout_overflow";
+
+#[derive(Serialize, Deserialize)]
+struct Overflow {
+    /// Events to emit before running the trigger again
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    events: Vec<QueuedEvent>,
+    /// IDs of events already emitted from a run of the trigger from `store`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    seen: Vec<String>,
+    /// How many events of a run of the trigger from `store` were already
+    /// emitted, once their IDs no longer fit
+    #[serde(default, skip_serializing_if = "is_zero")]
+    skip: usize,
+    /// The connector's store
+    store: String,
+}
+
+/// Kept as sent, so queued events reach the host exactly as direct ones would
+#[derive(Serialize, Deserialize)]
+struct QueuedEvent {
+    id: String,
+    serialized_data: String,
+}
+
+/// Where a run of the trigger from the previous store continues
+#[derive(Default)]
+struct Replay {
+    seen: Vec<String>,
+    skip: usize,
+}
+
+impl From<TriggerEvent> for QueuedEvent {
+    fn from(event: TriggerEvent) -> Self {
+        QueuedEvent {
+            id: event.id,
+            serialized_data: event.serialized_data,
+        }
+    }
+}
+
+impl From<QueuedEvent> for TriggerEvent {
+    fn from(event: QueuedEvent) -> Self {
+        TriggerEvent {
+            id: event.id,
+            serialized_data: event.serialized_data,
+        }
+    }
+}
+
+/// Run `fetch` with the connector's store and keep the response within the
+/// event limit
+///
+/// `fetch` sees and returns only the connector's store; the reserved section
+/// is added and removed here.
+pub fn fetch_events(
+    mut context: TriggerContext,
+    fetch: impl FnOnce(TriggerContext) -> Result<TriggerResponse, AppError>,
+) -> Result<TriggerResponse, AppError> {
+    let Some(mut overflow) = parse(&context.store) else {
+        let previous_store = context.store.clone();
+        let response = fetch(context)?;
+        return respond(Vec::new(), response, previous_store, Replay::default());
+    };
+
+    let pending: Vec<TriggerEvent> = overflow.events.drain(..).map(Into::into).collect();
+    if pending.len() >= MAX_EVENTS {
+        // Only kept events this time; the trigger runs once they are out
+        let response = TriggerResponse {
+            events: Vec::new(),
+            store: overflow.store.clone(),
+        };
+        return respond(pending, response, overflow.store, Replay::default());
+    }
+
+    context.store = overflow.store.clone();
+    let mut response = fetch(context)?;
+    response
+        .events
+        .retain(|event| !overflow.seen.contains(&event.id));
+    response
+        .events
+        .drain(..overflow.skip.min(response.events.len()));
+
+    let replay = Replay {
+        seen: overflow.seen,
+        skip: overflow.skip,
+    };
+    respond(pending, response, overflow.store, replay)
+}
+
+/// The response with `pending` events first and at most `MAX_EVENTS` events,
+/// keeping the rest in the store
+///
+/// `previous_store` is the store the trigger ran from and `replay` says which
+/// events from that run were already emitted.
+fn respond(
+    mut pending: Vec<TriggerEvent>,
+    response: TriggerResponse,
+    previous_store: String,
+    replay: Replay,
+) -> Result<TriggerResponse, AppError> {
+    if pending.len() + response.events.len() <= MAX_EVENTS {
+        pending.extend(response.events);
+        return Ok(TriggerResponse {
+            events: pending,
+            store: response.store,
+        });
+    }
+
+    let mut fetched = response.events;
+    let room = MAX_EVENTS.saturating_sub(pending.len());
+    let surplus = fetched.split_off(room.min(fetched.len()));
+    let surplus_pending = pending.split_off(MAX_EVENTS.min(pending.len()));
+    let keeps_pending = !surplus_pending.is_empty();
+
+    let queued = Overflow {
+        events: surplus_pending
+            .into_iter()
+            .chain(surplus)
+            .map(Into::into)
+            .collect(),
+        seen: Vec::new(),
+        skip: 0,
+        store: response.store.clone(),
+    };
+    let queued_len = queued.events.len();
+    let store = envelope(&queued)?;
+
+    // Kept events left over from earlier runs cannot be fetched again
+    if store.len() <= MAX_STORE_BYTES || keeps_pending {
+        let events = pending.into_iter().chain(fetched).collect();
+        return Ok(TriggerResponse { events, store });
+    }
+
+    // Too large to keep: run again from the previous store and skip what was emitted
+    let mut rerun = Overflow {
+        events: Vec::new(),
+        seen: replay.seen,
+        skip: replay.skip,
+        store: previous_store,
+    };
+    rerun
+        .seen
+        .extend(fetched.iter().map(|event| event.id.clone()));
+    let events: Vec<_> = pending.into_iter().chain(fetched).collect();
+
+    let mut store = envelope(&rerun)?;
+    if rerun.skip > 0 || store.len() > MAX_STORE_BYTES {
+        // The emitted events are a prefix of the run, so their count will do
+        rerun.skip += rerun.seen.len();
+        rerun.seen.clear();
+        store = envelope(&rerun)?;
+    }
+    if store.len() > MAX_STORE_BYTES {
+        return Err(AppError {
+            code: ErrorCode::InternalError,
+            message: format!(
+                "{} events over the {} event limit cannot be kept: the trigger's previous store \
+                 ({} bytes) is too large to run again from within the {} byte store limit",
+                queued_len,
+                MAX_EVENTS,
+                rerun.store.len(),
+                MAX_STORE_BYTES
+            ),
+        });
+    }
+
+    Ok(TriggerResponse { events, store })
+}
+
+/// The reserved section, if the store is one
+fn parse(store: &str) -> Option<Overflow> {
+    let Ok(Value::Object(mut stored)) = serde_json::from_str::<Value>(store) else {
+        return None;
+    };
+    if stored.len() != 1 {
+        return None;
+    }
+
+    serde_json::from_value(stored.remove(OVERFLOW_KEY)?).ok()
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+fn envelope(overflow: &Overflow) -> Result<String, AppError> {
+    let mut stored = serde_json::Map::new();
+    stored.insert(
+        OVERFLOW_KEY.to_string(),
+        serde_json::to_value(overflow)
+            .map_err(serialize_error("Failed to serialize trigger store"))?,
+    );
+    Ok(Value::Object(stored).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::trigger_context;
+    use serde_json::json;
+    use std::collections::BTreeSet;
+
+    /// A trigger that returns `count` events on its first run and none after
+    fn snapshot_trigger(
+        count: usize,
+        pad: usize,
+    ) -> impl Fn(TriggerContext) -> Result<TriggerResponse, AppError> {
+        move |context| {
+            let done = json!({ "done": true, "pad": "x".repeat(pad) }).to_string();
+            if context.store == done {
+                return Ok(TriggerResponse {
+                    events: Vec::new(),
+                    store: done,
+                });
+            }
+
+            let events = (0..count)
+                .map(|index| TriggerEvent {
+                    id: format!("{:040}", index),
+                    serialized_data: json!({ "index": index, "note": "y".repeat(200) }).to_string(),
+                })
+                .collect();
+            Ok(TriggerResponse {
+                events,
+                store: done,
+            })
+        }
+    }
+
+    /// Run rounds until the trigger has nothing left, returning the IDs of
+    /// every emitted event and every store
+    fn run(
+        trigger: impl Fn(TriggerContext) -> Result<TriggerResponse, AppError>,
+        mut store: String,
+        rounds: usize,
+    ) -> (Vec<String>, Vec<String>) {
+        let mut ids = Vec::new();
+        let mut stores = Vec::new();
+        for _ in 0..rounds {
+            let context = trigger_context("trigger", &json!({}), &json!({}), &store);
+            let response = fetch_events(context, &trigger).unwrap();
+            assert!(response.events.len() <= MAX_EVENTS);
+            assert!(
+                response.store.len() <= MAX_STORE_BYTES,
+                "store of {} bytes",
+                response.store.len()
+            );
+
+            stores.push(response.store.clone());
+            if response.events.is_empty() && parse(&response.store).is_none() {
+                return (ids, stores);
+            }
+            ids.extend(response.events.into_iter().map(|event| event.id));
+            store = response.store;
+        }
+        panic!("trigger still had events after {} rounds", rounds);
+    }
+
+    fn assert_all_once(ids: &[String], count: usize) {
+        let unique: BTreeSet<_> = ids.iter().collect();
+        assert_eq!(unique.len(), ids.len(), "events emitted twice");
+        assert_eq!(ids.len(), count);
+    }
+
+    #[test]
+    fn small_surplus_is_queued() {
+        let (ids, stores) = run(snapshot_trigger(250, 100), String::new(), 10);
+        assert_all_once(&ids, 250);
+        assert_eq!(stores.len(), 4);
+        assert!(stores[0].contains("\"events\""));
+    }
+
+    #[test]
+    fn large_surplus_replays_the_run() {
+        let (ids, stores) = run(snapshot_trigger(1_000, 1_000), String::new(), 20);
+        assert_all_once(&ids, 1_000);
+        assert!(stores[0].contains("\"seen\""));
+    }
+
+    #[test]
+    fn replay_store_stays_within_the_limit() {
+        // Next to a 50 kB connector store the seen IDs outgrow the store
+        // after a few rounds
+        let start = json!({ "pad": "x".repeat(50_000) }).to_string();
+        let (ids, stores) = run(snapshot_trigger(1_000, 50_000), start, 20);
+        assert_all_once(&ids, 1_000);
+        assert!(stores[0].contains("\"seen\""));
+        assert!(stores.iter().any(|store| store.contains("\"skip\":")));
+        assert!(stores.last().unwrap().starts_with("{\"done\":true"));
+    }
+
+    #[test]
+    fn previous_store_too_large_to_keep_fails() {
+        // 40 kB of quotes, which escaping doubles inside the reserved section
+        let quotes = json!({ "quotes": "\"".repeat(20_000) }).to_string();
+        let trigger = |context: TriggerContext| {
+            if context.store.is_empty() {
+                return Ok(TriggerResponse {
+                    events: Vec::new(),
+                    store: quotes.clone(),
+                });
+            }
+            snapshot_trigger(1_000, 0)(context)
+        };
+
+        let first =
+            fetch_events(trigger_context("t", &json!({}), &json!({}), ""), trigger).unwrap();
+        assert_eq!(first.store, quotes);
+
+        // The surplus cannot be kept, so nothing is emitted rather than lost
+        let err = fetch_events(
+            trigger_context("t", &json!({}), &json!({}), &first.store),
+            trigger,
+        )
+        .unwrap_err();
+        assert!(matches!(err.code, ErrorCode::InternalError));
+        assert!(
+            err.message
+                .starts_with("900 events over the 100 event limit")
+        );
+    }
+
+    #[test]
+    fn queued_events_are_delivered_as_sent() {
+        let serialized_data = r#"{"z":1,"a":18446744073709551616,"n":1.10}"#;
+        let trigger = |context: TriggerContext| {
+            let events = if context.store.is_empty() {
+                (0..150)
+                    .map(|index| TriggerEvent {
+                        id: index.to_string(),
+                        serialized_data: serialized_data.to_string(),
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            Ok(TriggerResponse {
+                events,
+                store: "{}".to_string(),
+            })
+        };
+
+        let first =
+            fetch_events(trigger_context("t", &json!({}), &json!({}), ""), trigger).unwrap();
+        let second = fetch_events(
+            trigger_context("t", &json!({}), &json!({}), &first.store),
+            trigger,
+        )
+        .unwrap();
+        assert_eq!(second.events.len(), 50);
+        for event in first.events.iter().chain(&second.events) {
+            assert_eq!(event.serialized_data, serialized_data);
+        }
+        assert_eq!(second.store, "{}");
+    }
+}
@@ -0,0 +1,340 @@
+use super::events::EventId;
+use super::store::TriggerStore;
+use crate::error::serialize_error;
+use crate::json::lookup;
+use crate::standout::app::types::{AppError, ErrorCode, TriggerEvent, TriggerResponse};
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Most bytes the recent event IDs may take in the store, leaving room for
+/// the connector's other data and the overflow section within 64 kB
+const RECENT_LIMIT_BYTES: usize = 24 * 1024;
+
+/// Incremental "updated since" polling
+///
+/// Keeps a watermark, the latest timestamp seen, in the trigger store. Each
+/// run queries from the watermark minus an overlap window, so items written
+/// late or with equal timestamps are not lost, and skips the items of the
+/// window that were already emitted. The watermark only advances once the
+/// events have been built.
+///
+/// The emitted IDs are kept within 24 kB. Past that only those at the
+/// watermark itself are kept, so items of the window returned again are
+/// emitted again; if even those do not fit, `respond` fails with
+/// `Misconfigured`, as the overlap window is too busy to poll this way.
+///
+/// ```ignore
+/// let polling = Polling::new("updated_at").overlap(TimeDelta::minutes(5));
+///
+/// let mut request = client.request(Method::Get, "/orders");
+/// if let Some(since) = polling.since(&context.store)? {
+///     request = request.query("updated_since", since.to_rfc3339());
+/// }
+/// let orders: Vec<Value> = request.send_as()?;
+///
+/// polling.respond(&context.store, orders)
+/// ```
+#[derive(Clone, Debug)]
+pub struct Polling {
+    timestamp_field: String,
+    id_field: String,
+    overlap: TimeDelta,
+    start: Option<DateTime<Utc>>,
+    event_id: Option<EventId>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct PollState {
+    /// The latest timestamp seen
+    watermark: Option<DateTime<Utc>>,
+    /// Event IDs emitted within the overlap window, with their timestamps
+    #[serde(default)]
+    recent: BTreeMap<String, DateTime<Utc>>,
+}
+
+impl Polling {
+    /// Poll on the timestamp at `timestamp_field`, a dotted path such as
+    /// `updated_at` or `attributes.modified`
+    ///
+    /// Items are identified by their `id` field, without an overlap window.
+    #[allow(dead_code)]
+    pub fn new(timestamp_field: impl Into<String>) -> Self {
+        Polling {
+            timestamp_field: timestamp_field.into(),
+            id_field: "id".to_string(),
+            overlap: TimeDelta::zero(),
+            start: None,
+            event_id: None,
+        }
+    }
+
+    /// The dotted path of the item ID
+    #[allow(dead_code)]
+    pub fn id_field(mut self, id_field: impl Into<String>) -> Self {
+        self.id_field = id_field.into();
+        self
+    }
+
+    /// Query this far before the watermark
+    #[allow(dead_code)]
+    pub fn overlap(mut self, overlap: TimeDelta) -> Self {
+        self.overlap = overlap;
+        self
+    }
+
+    /// Where the first run starts; without it the first run has no `since`
+    #[allow(dead_code)]
+    pub fn start_from(mut self, start: DateTime<Utc>) -> Self {
+        self.start = Some(start);
+        self
+    }
+
+    /// Derive event IDs with this strategy instead of the item ID and timestamp
+    #[allow(dead_code)]
+    pub fn event_id(mut self, event_id: EventId) -> Self {
+        self.event_id = Some(event_id);
+        self
+    }
+
+    /// The time to query from: the watermark minus the overlap
+    #[allow(dead_code)]
+    pub fn since(&self, store: &str) -> Result<Option<DateTime<Utc>>, AppError> {
+        let state = Self::store().load(store)?;
+        Ok(match state.watermark {
+            Some(watermark) => Some(watermark - self.overlap),
+            None => self.start,
+        })
+    }
+
+    /// Turn the items returned since `since` into events, oldest first, and
+    /// advance the watermark
+    ///
+    /// Event IDs are the item ID and its timestamp, so every update of an
+    /// item is a new event, unless another strategy is set with `event_id`.
+    #[allow(dead_code)]
+    pub fn respond(&self, store: &str, items: Vec<Value>) -> Result<TriggerResponse, AppError> {
+        let store_format = Self::store();
+        let mut state = store_format.load(store)?;
+
+        let mut stamped = items
+            .into_iter()
+            .map(|item| {
+                let timestamp = self.timestamp(&item)?;
+                let event_id = match &self.event_id {
+                    Some(event_id) => event_id.id(&item)?,
+                    None => format!("{}-{}", self.id(&item)?, timestamp.to_rfc3339()),
+                };
+                Ok((timestamp, event_id, item))
+            })
+            .collect::<Result<Vec<_>, AppError>>()?;
+        stamped.sort_by_key(|(timestamp, _, _)| *timestamp);
+
+        let mut events = Vec::with_capacity(stamped.len());
+        let mut emitted = BTreeMap::new();
+        for (timestamp, event_id, item) in stamped {
+            if state.recent.contains_key(&event_id) || emitted.contains_key(&event_id) {
+                continue;
+            }
+            events.push(TriggerEvent {
+                id: event_id.clone(),
+                serialized_data: serde_json::to_string(&item)
+                    .map_err(serialize_error("Failed to serialize event"))?,
+            });
+            emitted.insert(event_id, timestamp);
+        }
+
+        // Only now that every event is built does the watermark move
+        let latest = emitted.values().max().copied();
+        state.watermark = state.watermark.max(latest);
+        state.recent.extend(emitted);
+        if let Some(watermark) = state.watermark {
+            let window_start = watermark - self.overlap;
+            state
+                .recent
+                .retain(|_, timestamp| *timestamp >= window_start);
+
+            if recent_size(&state.recent)? > RECENT_LIMIT_BYTES {
+                state.recent.retain(|_, timestamp| *timestamp == watermark);
+            }
+            if recent_size(&state.recent)? > RECENT_LIMIT_BYTES {
+                return Err(AppError {
+                    code: ErrorCode::Misconfigured,
+                    message: format!(
+                        "{} items share the timestamp {}, too many to tell apart in the trigger store; poll on a more precise timestamp field than {}",
+                        state.recent.len(),
+                        watermark.to_rfc3339(),
+                        self.timestamp_field
+                    ),
+                });
+            }
+        }
+
+        Ok(TriggerResponse {
+            events,
+            store: store_format.save(&state)?,
+        })
+    }
+
+    fn store() -> TriggerStore<PollState> {
+        TriggerStore::new(1)
+    }
+
+    fn timestamp(&self, item: &Value) -> Result<DateTime<Utc>, AppError> {
+        let value = lookup(item, &self.timestamp_field);
+        parse_timestamp(value).ok_or_else(|| AppError {
+            code: ErrorCode::MalformedResponse,
+            message: format!(
+                "Invalid timestamp at {}: {}",
+                self.timestamp_field,
+                value.map_or("missing".to_string(), Value::to_string)
+            ),
+        })
+    }
+
+    fn id(&self, item: &Value) -> Result<String, AppError> {
+        match lookup(item, &self.id_field) {
+            Some(Value::String(id)) => Ok(id.clone()),
+            Some(Value::Number(id)) => Ok(id.to_string()),
+            _ => Err(AppError {
+                code: ErrorCode::MalformedResponse,
+                message: format!("Missing item ID at {}", self.id_field),
+            }),
+        }
+    }
+}
+
+fn recent_size(recent: &BTreeMap<String, DateTime<Utc>>) -> Result<usize, AppError> {
+    serde_json::to_string(recent)
+        .map(|recent| recent.len())
+        .map_err(serialize_error("Failed to serialize trigger store"))
+}
+
+/// RFC 3339, `YYYY-MM-DD HH:MM:SS` in UTC, or Unix seconds or milliseconds
+fn parse_timestamp(value: Option<&Value>) -> Option<DateTime<Utc>> {
+    match value? {
+        Value::String(text) => DateTime::parse_from_rfc3339(text)
+            .map(|timestamp| timestamp.with_timezone(&Utc))
+            .or_else(|_| {
+                NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f")
+                    .map(|timestamp| timestamp.and_utc())
+            })
+            .ok(),
+        Value::Number(number) => {
+            let number = number.as_i64()?;
+            // Anything past the year 33658 in seconds is taken as milliseconds
+            if number.abs() >= 1_000_000_000_000 {
+                DateTime::from_timestamp_millis(number)
+            } else {
+                DateTime::from_timestamp(number, 0)
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn ids(response: &TriggerResponse) -> Vec<&str> {
+        response
+            .events
+            .iter()
+            .map(|event| event.id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn overlap_window_is_not_emitted_twice() {
+        let polling = Polling::new("updated_at").overlap(TimeDelta::minutes(5));
+        assert_eq!(polling.since("").unwrap(), None);
+
+        let first = polling
+            .respond(
+                "",
+                vec![
+                    json!({ "id": 2, "updated_at": "2024-05-01T10:04:00Z" }),
+                    json!({ "id": 1, "updated_at": "2024-05-01T10:00:00Z" }),
+                ],
+            )
+            .unwrap();
+        assert_eq!(
+            ids(&first),
+            ["1-2024-05-01T10:00:00+00:00", "2-2024-05-01T10:04:00+00:00"]
+        );
+        assert_eq!(
+            polling.since(&first.store).unwrap(),
+            Some("2024-05-01T09:59:00Z".parse().unwrap())
+        );
+
+        let second = polling
+            .respond(
+                &first.store,
+                vec![
+                    json!({ "id": 2, "updated_at": "2024-05-01T10:04:00Z" }),
+                    json!({ "id": 3, "updated_at": "2024-05-01 10:04:00" }),
+                    json!({ "id": 1, "updated_at": 1714557900 }),
+                ],
+            )
+            .unwrap();
+        assert_eq!(
+            ids(&second),
+            ["3-2024-05-01T10:04:00+00:00", "1-2024-05-01T10:05:00+00:00"]
+        );
+    }
+
+    #[test]
+    fn busy_windows_stay_within_the_store_limit() {
+        let polling = Polling::new("updated_at").overlap(TimeDelta::minutes(10));
+        let start: DateTime<Utc> = "2024-05-01T10:00:00Z".parse().unwrap();
+
+        let mut store = String::new();
+        for run in 0..3 {
+            let items = (0..1_500)
+                .map(|index| {
+                    let timestamp = start + TimeDelta::milliseconds(run * 600_000 + index * 400);
+                    json!({ "id": format!("order-{}-{}", run, index), "updated_at": timestamp.to_rfc3339() })
+                })
+                .collect();
+            let response = polling.respond(&store, items).unwrap();
+            assert_eq!(response.events.len(), 1_500);
+            assert!(
+                response.store.len() <= 32 * 1024,
+                "{} bytes",
+                response.store.len()
+            );
+            store = response.store;
+        }
+    }
+
+    #[test]
+    fn too_many_items_at_one_timestamp_is_misconfigured() {
+        let polling = Polling::new("updated_at");
+        let items = (0..1_000)
+            .map(|index| json!({ "id": index, "updated_at": "2024-05-01T10:00:00Z" }))
+            .collect();
+
+        let error = polling.respond("", items).unwrap_err();
+        assert!(matches!(error.code, ErrorCode::Misconfigured));
+        assert!(error.message.contains("1000 items share the timestamp"));
+    }
+
+    #[test]
+    fn invalid_timestamps_are_malformed() {
+        let polling = Polling::new("meta.updated_at");
+        let error = polling
+            .respond(
+                "",
+                vec![json!({ "id": 1, "meta": { "updated_at": "yesterday" } })],
+            )
+            .unwrap_err();
+        assert!(matches!(error.code, ErrorCode::MalformedResponse));
+        assert_eq!(
+            error.message,
+            "Invalid timestamp at meta.updated_at: \"yesterday\""
+        );
+    }
+}
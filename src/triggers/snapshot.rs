@@ -0,0 +1,624 @@
+use super::events::{EventId, content_hash, escape, fnv1a};
+use super::store::TriggerStore;
+use crate::error::serialize_error;
+use crate::json::lookup_mut;
+use crate::standout::app::types::{AppError, ErrorCode, TriggerEvent, TriggerResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Most bytes the snapshot may take in the store once escaped, which is how
+/// the overflow section of `triggers::overflow` keeps it next to its own data
+const SNAPSHOT_LIMIT_BYTES: usize = 40 * 1024;
+
+/// Bytes of each content hash kept in the store
+const HASH_BYTES: usize = 6;
+
+/// Bytes of the ID hash and of the content hash of each record once the
+/// snapshot only keeps hashes
+const ID_HASH_BYTES: usize = 4;
+const COMPACT_HASH_BYTES: usize = 2;
+const COMPACT_ENTRY_BYTES: usize = ID_HASH_BYTES + COMPACT_HASH_BYTES;
+
+/// Detects created, updated and deleted records by comparing snapshots
+///
+/// For APIs without an "updated since" filter: list the whole collection
+/// every run and let `Snapshot` compare it with the fingerprints kept in the
+/// store, the sorted record IDs and a 48-bit hash of each record's content.
+/// Event data is `{"change": "created" | "updated" | "deleted", "id": ...,
+/// "item": ...}`; deleted events have no `item`. Event IDs end with the
+/// number of the run, so a change that happened before is a new event.
+///
+/// The fingerprints take about the ID length plus 13 bytes per record and
+/// must stay within 40 kB: about 1,700 records with 10-character IDs, or 800
+/// with UUIDs. Past that only a 32-bit hash of each ID and a 16-bit hash of
+/// its content are kept, 8 bytes per record, which fits about 5,000 records:
+/// one update in 65,536 goes unnoticed, and deleted records are reported by
+/// `id_hash` instead of `id`, since their IDs are no longer known. Larger
+/// collections fail with `Misconfigured`; narrow the listing, e.g. to one
+/// trigger per account or status.
+///
+/// ```ignore
+/// let contacts = client
+///     .paginate("/contacts", pagination)
+///     .collect::<Result<Vec<_>, _>>()?;
+///
+/// Snapshot::new()
+///     .ignore_field("last_seen_at")
+///     .respond(&context.store, contacts)
+/// ```
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    id: EventId,
+    ignored_fields: Vec<String>,
+    emit_initial: bool,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct SnapshotState {
+    /// `None` until the first run
+    fingerprints: Option<Fingerprints>,
+    /// Number of runs so far
+    #[serde(default)]
+    runs: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Fingerprints {
+    Exact {
+        /// Record IDs, sorted
+        ids: Vec<String>,
+        /// The first `HASH_BYTES` of each record's content hash, in `ids` order
+        #[serde(with = "base64_bytes")]
+        hashes: Vec<u8>,
+    },
+    /// For collections whose IDs do not fit
+    Compact {
+        /// The ID hash and content hash of each record, sorted
+        #[serde(with = "base64_bytes")]
+        entries: Vec<u8>,
+    },
+}
+
+/// A record of the current snapshot
+struct Record {
+    id: String,
+    hash: u64,
+    item: Value,
+}
+
+impl Default for Snapshot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Snapshot {
+    /// Records identified by their `id` field; the first run only takes a
+    /// snapshot
+    #[allow(dead_code)]
+    pub fn new() -> Self {
+        Snapshot {
+            id: EventId::field("id"),
+            ignored_fields: Vec::new(),
+            emit_initial: false,
+        }
+    }
+
+    /// Identify records with this strategy instead of the `id` field
+    #[allow(dead_code)]
+    pub fn id(mut self, id: EventId) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// Leave a dotted path out of the content hash, for fields that change
+    /// without the record changing
+    #[allow(dead_code)]
+    pub fn ignore_field(mut self, path: impl Into<String>) -> Self {
+        self.ignored_fields.push(path.into());
+        self
+    }
+
+    /// Emit every record as created on the first run
+    #[allow(dead_code)]
+    pub fn emit_initial(mut self, emit_initial: bool) -> Self {
+        self.emit_initial = emit_initial;
+        self
+    }
+
+    /// Compare the listed records with the previous snapshot and save the new one
+    #[allow(dead_code)]
+    pub fn respond(&self, store: &str, items: Vec<Value>) -> Result<TriggerResponse, AppError> {
+        let store_format = Self::store();
+        let state = store_format.load(store)?;
+        let previous = state.fingerprints;
+        let runs = state.runs + 1;
+
+        let mut records = Vec::with_capacity(items.len());
+        let mut ids = BTreeSet::new();
+        for item in items {
+            let id = self.id.id(&item)?;
+            if !ids.insert(id.clone()) {
+                continue;
+            }
+            records.push(Record {
+                hash: self.hash(&item),
+                id,
+                item,
+            });
+        }
+
+        let mut events = Vec::new();
+        match &previous {
+            None if self.emit_initial => {
+                for record in &records {
+                    events.push(record_event("created", record)?);
+                }
+            }
+            None => {}
+            Some(fingerprints @ Fingerprints::Exact { .. }) => {
+                let previous = fingerprints.exact_entries()?;
+                for record in &records {
+                    match previous.get(record.id.as_str()) {
+                        None => events.push(record_event("created", record)?),
+                        Some(hash) if *hash != prefix(record.hash, HASH_BYTES) => {
+                            events.push(record_event("updated", record)?)
+                        }
+                        Some(_) => {}
+                    }
+                }
+                for (id, hash) in previous {
+                    if !ids.contains(id) {
+                        events.push(deleted_event(id, hash));
+                    }
+                }
+            }
+            Some(fingerprints @ Fingerprints::Compact { .. }) => {
+                let previous = fingerprints.compact_entries()?;
+                let mut id_hashes = BTreeSet::new();
+                for record in &records {
+                    let id_hash = id_hash(&record.id);
+                    id_hashes.insert(id_hash);
+                    match previous.get(&id_hash) {
+                        None => events.push(record_event("created", record)?),
+                        Some(hash) if *hash != prefix(record.hash, COMPACT_HASH_BYTES) => {
+                            events.push(record_event("updated", record)?)
+                        }
+                        Some(_) => {}
+                    }
+                }
+                for (id_hash, hash) in previous {
+                    if !id_hashes.contains(&id_hash) {
+                        events.push(hashed_deleted_event(id_hash, hash));
+                    }
+                }
+            }
+        }
+
+        // The host drops event IDs it has seen before, and a record can change
+        // back to earlier content, or be deleted again after being recreated
+        for event in &mut events {
+            event.id = format!("{}|{}", event.id, runs);
+        }
+
+        let mut escaped = 0;
+        for fingerprints in [
+            Fingerprints::exact(&records),
+            Fingerprints::compact(&records),
+        ] {
+            let state = SnapshotState {
+                fingerprints: Some(fingerprints),
+                runs,
+            };
+            let store = store_format.encode(&state)?;
+            escaped = Value::String(store.clone()).to_string().len();
+            if escaped <= SNAPSHOT_LIMIT_BYTES {
+                return Ok(TriggerResponse { events, store });
+            }
+        }
+
+        Err(AppError {
+            code: ErrorCode::Misconfigured,
+            message: format!(
+                "Snapshot of {} records takes {} bytes of the trigger store even as hashes, over the {} byte limit; list fewer records per trigger",
+                records.len(),
+                escaped,
+                SNAPSHOT_LIMIT_BYTES
+            ),
+        })
+    }
+
+    fn store() -> TriggerStore<SnapshotState> {
+        TriggerStore::new(1)
+    }
+
+    fn hash(&self, item: &Value) -> u64 {
+        if self.ignored_fields.is_empty() {
+            return content_hash(item);
+        }
+
+        let mut item = item.clone();
+        for path in &self.ignored_fields {
+            remove(&mut item, path);
+        }
+        content_hash(&item)
+    }
+}
+
+impl Fingerprints {
+    fn exact(records: &[Record]) -> Self {
+        let mut sorted: Vec<_> = records.iter().collect();
+        sorted.sort_by(|a, b| a.id.cmp(&b.id));
+
+        Fingerprints::Exact {
+            ids: sorted.iter().map(|record| record.id.clone()).collect(),
+            hashes: sorted
+                .iter()
+                .flat_map(|record| prefix_bytes(record.hash, HASH_BYTES))
+                .collect(),
+        }
+    }
+
+    fn compact(records: &[Record]) -> Self {
+        let mut entries: Vec<_> = records
+            .iter()
+            .map(|record| {
+                let mut entry = [0; COMPACT_ENTRY_BYTES];
+                entry[..ID_HASH_BYTES].copy_from_slice(&id_hash(&record.id).to_be_bytes());
+                entry[ID_HASH_BYTES..]
+                    .copy_from_slice(&prefix_bytes(record.hash, COMPACT_HASH_BYTES));
+                entry
+            })
+            .collect();
+        entries.sort();
+
+        Fingerprints::Compact {
+            entries: entries.concat(),
+        }
+    }
+
+    /// The content hash prefix by record ID
+    fn exact_entries(&self) -> Result<BTreeMap<&str, u64>, AppError> {
+        let Fingerprints::Exact { ids, hashes } = self else {
+            return Ok(BTreeMap::new());
+        };
+        if hashes.len() != ids.len() * HASH_BYTES {
+            return Err(malformed_store(hashes.len(), ids.len()));
+        }
+
+        Ok(ids
+            .iter()
+            .zip(hashes.chunks(HASH_BYTES))
+            .map(|(id, hash)| (id.as_str(), from_bytes(hash)))
+            .collect())
+    }
+
+    /// The content hash prefix by ID hash
+    fn compact_entries(&self) -> Result<BTreeMap<u32, u64>, AppError> {
+        let Fingerprints::Compact { entries } = self else {
+            return Ok(BTreeMap::new());
+        };
+        if entries.len() % COMPACT_ENTRY_BYTES != 0 {
+            return Err(malformed_store(
+                entries.len(),
+                entries.len() / COMPACT_ENTRY_BYTES,
+            ));
+        }
+
+        Ok(entries
+            .chunks(COMPACT_ENTRY_BYTES)
+            .map(|entry| {
+                let (id_hash, hash) = entry.split_at(ID_HASH_BYTES);
+                (from_bytes(id_hash) as u32, from_bytes(hash))
+            })
+            .collect())
+    }
+}
+
+fn malformed_store(bytes: usize, records: usize) -> AppError {
+    AppError {
+        code: ErrorCode::InternalError,
+        message: format!(
+            "Snapshot store has {} bytes of hashes for {} records",
+            bytes, records
+        ),
+    }
+}
+
+/// The first `bytes` bytes of a hash, as a number
+fn prefix(hash: u64, bytes: usize) -> u64 {
+    hash >> (64 - 8 * bytes)
+}
+
+fn prefix_bytes(hash: u64, bytes: usize) -> Vec<u8> {
+    hash.to_be_bytes()[..bytes].to_vec()
+}
+
+fn from_bytes(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0, |value, byte| (value << 8) | u64::from(*byte))
+}
+
+/// A 32-bit hash of a record ID
+///
+/// FNV-1a barely mixes the last bytes into the top bits, so IDs that only
+/// differ at the end would share them without the final mix.
+fn id_hash(id: &str) -> u32 {
+    let mut hash = fnv1a(id.as_bytes());
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^= hash >> 33;
+    (hash >> 32) as u32
+}
+
+fn record_event(change: &str, record: &Record) -> Result<TriggerEvent, AppError> {
+    let data = json!({ "change": change, "id": record.id, "item": record.item });
+    Ok(TriggerEvent {
+        id: format!("{}|{}|{:016x}", change, escape(&record.id), record.hash),
+        serialized_data: serde_json::to_string(&data)
+            .map_err(serialize_error("Failed to serialize event"))?,
+    })
+}
+
+fn deleted_event(id: &str, hash: u64) -> TriggerEvent {
+    TriggerEvent {
+        id: format!("deleted|{}|{:012x}", escape(id), hash),
+        serialized_data: json!({ "change": "deleted", "id": id }).to_string(),
+    }
+}
+
+/// A deleted record of which only the hashes were kept
+fn hashed_deleted_event(id_hash: u32, hash: u64) -> TriggerEvent {
+    let id_hash = format!("{:08x}", id_hash);
+    TriggerEvent {
+        id: format!("deleted|#{}|{:04x}", id_hash, hash),
+        serialized_data: json!({ "change": "deleted", "id_hash": id_hash }).to_string(),
+    }
+}
+
+/// Remove the value at a dotted path
+fn remove(value: &mut Value, path: &str) {
+    let (parent, key) = match path.rsplit_once('.') {
+        Some((parent, key)) => (lookup_mut(value, parent), key),
+        None => (Some(value), path),
+    };
+
+    if let Some(Value::Object(fields)) = parent {
+        fields.remove(key);
+    }
+}
+
+mod base64_bytes {
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&crate::base64::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        crate::base64::decode(&encoded).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changes(response: &TriggerResponse) -> Vec<(String, String)> {
+        response
+            .events
+            .iter()
+            .map(|event| {
+                let data: Value = serde_json::from_str(&event.serialized_data).unwrap();
+                let id = match &data["id"] {
+                    Value::String(id) => id.clone(),
+                    id => id.to_string(),
+                };
+                (data["change"].as_str().unwrap().to_string(), id)
+            })
+            .collect()
+    }
+
+    fn contacts(count: usize, version: usize) -> Vec<Value> {
+        (0..count)
+            .map(|index| json!({ "id": format!("c{:09}", index), "version": version }))
+            .collect()
+    }
+
+    #[test]
+    fn created_updated_and_deleted_records_are_found() {
+        let snapshot = Snapshot::new().ignore_field("meta.seen_at");
+        let first = snapshot
+            .respond(
+                "",
+                vec![
+                    json!({ "id": 1, "name": "Ada", "meta": { "seen_at": 1 } }),
+                    json!({ "id": 2, "name": "Grace" }),
+                ],
+            )
+            .unwrap();
+        assert!(first.events.is_empty());
+
+        let second = snapshot
+            .respond(
+                &first.store,
+                vec![
+                    json!({ "id": 1, "name": "Ada", "meta": { "seen_at": 2 } }),
+                    json!({ "id": 3, "name": "Edsger" }),
+                ],
+            )
+            .unwrap();
+        assert_eq!(
+            changes(&second),
+            [
+                ("created".to_string(), "3".to_string()),
+                ("deleted".to_string(), "2".to_string())
+            ]
+        );
+
+        let third = snapshot
+            .respond(&second.store, vec![json!({ "id": 3, "name": "Edsger W." })])
+            .unwrap();
+        assert_eq!(
+            changes(&third),
+            [
+                ("updated".to_string(), "3".to_string()),
+                ("deleted".to_string(), "1".to_string())
+            ]
+        );
+
+        let initial = Snapshot::new()
+            .emit_initial(true)
+            .respond("", vec![json!({ "id": 1 })])
+            .unwrap();
+        assert_eq!(
+            changes(&initial),
+            [("created".to_string(), "1".to_string())]
+        );
+    }
+
+    #[test]
+    fn no_change_is_missed_at_capacity() {
+        let snapshot = Snapshot::new();
+        let first = snapshot.respond("", contacts(1_700, 1)).unwrap();
+        assert!(Value::String(first.store.clone()).to_string().len() <= SNAPSHOT_LIMIT_BYTES);
+
+        // Every record updated, the last 100 deleted and 100 created
+        let mut items = contacts(1_600, 2);
+        items.extend((0..100).map(|index| json!({ "id": format!("n{:09}", index) })));
+        let second = snapshot.respond(&first.store, items.clone()).unwrap();
+        assert_eq!(changes_of(&second, "updated"), 1_600);
+        assert_eq!(changes_of(&second, "created"), 100);
+        assert_eq!(changes_of(&second, "deleted"), 100);
+
+        // And nothing is reported when nothing changed
+        let third = snapshot.respond(&second.store, items).unwrap();
+        assert!(third.events.is_empty());
+    }
+
+    fn changes_of(response: &TriggerResponse, change: &str) -> usize {
+        changes(response)
+            .iter()
+            .filter(|(found, _)| found == change)
+            .count()
+    }
+
+    #[test]
+    fn large_diffs_get_through_the_event_limit() {
+        use super::super::overflow::{self, MAX_EVENTS};
+        use super::super::store::MAX_STORE_BYTES;
+        use crate::harness::trigger_context;
+
+        let snapshot = Snapshot::new();
+        let mut store = snapshot.respond("", contacts(1_700, 1)).unwrap().store;
+        let items = contacts(1_700, 2);
+
+        let mut delivered = BTreeSet::new();
+        for _ in 0..30 {
+            let context = trigger_context("contacts", &json!({}), &json!({}), &store);
+            let response = overflow::fetch_events(context, |context| {
+                snapshot.respond(&context.store, items.clone())
+            })
+            .unwrap();
+            assert!(response.events.len() <= MAX_EVENTS);
+            assert!(response.store.len() <= MAX_STORE_BYTES);
+            if response.events.is_empty() {
+                break;
+            }
+            for event in response.events {
+                assert!(delivered.insert(event.id), "event emitted twice");
+            }
+            store = response.store;
+        }
+        assert_eq!(delivered.len(), 1_700);
+    }
+
+    #[test]
+    fn repeated_changes_get_new_event_ids() {
+        let snapshot = Snapshot::new();
+        let a = json!({ "id": 1, "name": "A" });
+        let b = json!({ "id": 1, "name": "B" });
+
+        let mut store = snapshot.respond("", vec![a.clone()]).unwrap().store;
+        let mut ids = Vec::new();
+        for items in [
+            vec![b.clone()],
+            vec![a],
+            vec![b.clone()],
+            vec![],
+            vec![b],
+            vec![],
+        ] {
+            let response = snapshot.respond(&store, items).unwrap();
+            assert_eq!(response.events.len(), 1);
+            ids.push(response.events[0].id.clone());
+            store = response.store;
+        }
+
+        // A to B twice, and B deleted twice, are still separate events
+        assert_eq!(ids.iter().collect::<BTreeSet<_>>().len(), ids.len());
+    }
+
+    #[test]
+    fn large_collections_fall_back_to_hashes() {
+        let snapshot = Snapshot::new();
+        let first = snapshot.respond("", contacts(1_000, 1)).unwrap();
+        assert!(first.store.contains("\"ids\""));
+
+        // Past the exact limit, deletions from the exact snapshot keep their IDs
+        let second = snapshot.respond(&first.store, contacts(5_000, 1)).unwrap();
+        assert!(second.store.contains("\"entries\""));
+        assert!(Value::String(second.store.clone()).to_string().len() <= SNAPSHOT_LIMIT_BYTES);
+        assert_eq!(changes_of(&second, "created"), 4_000);
+
+        // From hashes, deleted records are only known by the hash of their ID
+        let mut items = contacts(4_900, 2);
+        items.extend((0..50).map(|index| json!({ "id": format!("n{:09}", index) })));
+        let third = snapshot.respond(&second.store, items).unwrap();
+        assert_eq!(changes_of(&third, "updated"), 4_900);
+        assert_eq!(changes_of(&third, "created"), 50);
+
+        let deleted: BTreeSet<_> = third
+            .events
+            .iter()
+            .map(|event| serde_json::from_str::<Value>(&event.serialized_data).unwrap())
+            .filter(|data| data["change"] == "deleted")
+            .map(|data| data["id_hash"].as_str().unwrap().to_string())
+            .collect();
+        let expected: BTreeSet<_> = (4_900..5_000)
+            .map(|index| format!("{:08x}", id_hash(&format!("c{:09}", index))))
+            .collect();
+        assert_eq!(deleted, expected);
+    }
+
+    #[test]
+    fn hashes_miss_few_updates() {
+        let snapshot = Snapshot::new();
+        let mut store = snapshot.respond("", contacts(5_000, 0)).unwrap().store;
+        assert!(store.contains("\"entries\""));
+
+        // 50,000 updates, of which about 1 in 65,536 is expected to be missed
+        let mut updated = 0;
+        for version in 1..=10 {
+            let response = snapshot.respond(&store, contacts(5_000, version)).unwrap();
+            updated += changes_of(&response, "updated");
+            assert_eq!(changes_of(&response, "created"), 0);
+            assert_eq!(changes_of(&response, "deleted"), 0);
+            store = response.store;
+        }
+        assert!(50_000 - updated <= 5, "{} updates missed", 50_000 - updated);
+    }
+
+    #[test]
+    fn collections_over_the_limit_are_misconfigured() {
+        let error = Snapshot::new().respond("", contacts(6_000, 1)).unwrap_err();
+        assert!(matches!(error.code, ErrorCode::Misconfigured));
+        assert!(error.message.starts_with("Snapshot of 6000 records"));
+    }
+}
@@ -0,0 +1,301 @@
+use crate::standout::app::types::{AppError, ErrorCode};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+use std::marker::PhantomData;
+
+/// The most data the host accepts in `trigger-response.store`
+pub const MAX_STORE_BYTES: usize = 64 * 1024;
+
+type Migration = Box<dyn Fn(Value) -> Result<Value, AppError>>;
+
+/// Typed, versioned trigger store
+///
+/// The store is kept as `{"version": N, "data": ...}`. An empty store loads
+/// as `T::default()`, and data saved by an older version is passed through
+/// the migrations registered for each version in between. Stores written
+/// before `TriggerStore` was used (plain JSON without the envelope) count as
+/// version 0.
+///
+/// ```ignore
+/// #[derive(Default, Serialize, Deserialize)]
+/// struct State {
+///     cursor: Option<String>,
+/// }
+///
+/// let store = TriggerStore::<State>::new(1)
+///     .migration(0, |old| Ok(json!({ "cursor": old["since"] })));
+///
+/// let mut state = store.load(&context.store)?;
+/// // ... fetch events with state.cursor ...
+/// state.cursor = Some(next_cursor);
+///
+/// Ok(TriggerResponse { events, store: store.save(&state)? })
+/// ```
+pub struct TriggerStore<T> {
+    version: u32,
+    migrations: Vec<(u32, Migration)>,
+    data: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize + DeserializeOwned> TriggerStore<T> {
+    /// A store whose current schema version is `version`
+    #[allow(dead_code)]
+    pub fn new(version: u32) -> Self {
+        TriggerStore {
+            version,
+            migrations: Vec::new(),
+            data: PhantomData,
+        }
+    }
+
+    /// Register how data of version `from` becomes version `from + 1`
+    #[allow(dead_code)]
+    pub fn migration(
+        mut self,
+        from: u32,
+        migrate: impl Fn(Value) -> Result<Value, AppError> + 'static,
+    ) -> Self {
+        self.migrations.push((from, Box::new(migrate)));
+        self
+    }
+
+    /// Read the store, or `T::default()` if it is empty
+    #[allow(dead_code)]
+    pub fn load(&self, store: &str) -> Result<T, AppError>
+    where
+        T: Default,
+    {
+        if store.trim().is_empty() {
+            return Ok(T::default());
+        }
+        self.load_value(store)
+    }
+
+    /// Read the store, failing if it is empty
+    #[allow(dead_code)]
+    pub fn load_value(&self, store: &str) -> Result<T, AppError> {
+        let stored: Value = serde_json::from_str(store).map_err(|e| AppError {
+            code: ErrorCode::InternalError,
+            message: format!("Trigger store is not valid JSON: {}", e),
+        })?;
+
+        let (mut version, mut data) = match stored {
+            Value::Object(mut envelope)
+                if envelope.len() == 2
+                    && envelope.get("version").is_some_and(Value::is_u64)
+                    && envelope.contains_key("data") =>
+            {
+                let version = envelope["version"].as_u64().unwrap_or_default();
+                let version = u32::try_from(version).unwrap_or(u32::MAX);
+                (version, envelope.remove("data").unwrap_or_default())
+            }
+            legacy => (0, legacy),
+        };
+
+        if version > self.version {
+            return Err(AppError {
+                code: ErrorCode::Misconfigured,
+                message: format!(
+                    "Trigger store has version {}, newer than the supported version {}",
+                    version, self.version
+                ),
+            });
+        }
+
+        while version < self.version {
+            let (_, migrate) = self
+                .migrations
+                .iter()
+                .find(|(from, _)| *from == version)
+                .ok_or_else(|| AppError {
+                    code: ErrorCode::Misconfigured,
+                    message: format!("No trigger store migration from version {}", version),
+                })?;
+            data = migrate(data)?;
+            version += 1;
+        }
+
+        serde_path_to_error::deserialize(&data).map_err(|e| AppError {
+            code: ErrorCode::InternalError,
+            message: format!(
+                "Failed to read trigger store at {}: {}",
+                e.path(),
+                e.inner()
+            ),
+        })
+    }
+
+    /// Serialize the data for `trigger-response.store`
+    ///
+    /// Fails if the result is over the host's 64 kB limit, since the host
+    /// would otherwise ignore the whole response.
+    #[allow(dead_code)]
+    pub fn save(&self, data: &T) -> Result<String, AppError> {
+        let store = self.encode(data)?;
+        check_size(&store)?;
+        Ok(store)
+    }
+
+    /// Serialize the data like `save`, whatever its size
+    #[allow(dead_code)]
+    pub fn encode(&self, data: &T) -> Result<String, AppError> {
+        let data = serde_json::to_value(data).map_err(|e| AppError {
+            code: ErrorCode::InternalError,
+            message: format!("Failed to serialize trigger store: {}", e),
+        })?;
+        Ok(json!({ "version": self.version, "data": data }).to_string())
+    }
+}
+
+/// Fail if the store is over the host's 64 kB limit
+pub fn check_size(store: &str) -> Result<(), AppError> {
+    if store.len() > MAX_STORE_BYTES {
+        return Err(AppError {
+            code: ErrorCode::InternalError,
+            message: format!(
+                "Trigger store is {} bytes, over the {} byte limit; the host would ignore this response",
+                store.len(),
+                MAX_STORE_BYTES
+            ),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct State {
+        cursor: Option<String>,
+        #[serde(default)]
+        seen: Vec<u32>,
+    }
+
+    /// Version 0 had `since`, version 1 renamed it to `cursor`, version 2 added `seen`
+    fn store() -> TriggerStore<State> {
+        TriggerStore::new(2)
+            .migration(1, |mut old| {
+                old["seen"] = json!([]);
+                Ok(old)
+            })
+            .migration(0, |old| Ok(json!({ "cursor": old["since"] })))
+    }
+
+    #[test]
+    fn empty_stores_load_the_default() {
+        assert_eq!(store().load("").unwrap(), State::default());
+        assert_eq!(store().load("  \n").unwrap(), State::default());
+        assert!(store().load_value("").is_err());
+    }
+
+    #[test]
+    fn saved_data_loads_back() {
+        let state = State {
+            cursor: Some("c1".to_string()),
+            seen: vec![1, 2],
+        };
+        let saved = store().save(&state).unwrap();
+
+        assert_eq!(
+            saved,
+            r#"{"data":{"cursor":"c1","seen":[1,2]},"version":2}"#
+        );
+        assert_eq!(store().load(&saved).unwrap(), state);
+    }
+
+    #[test]
+    fn stores_without_the_envelope_are_migrated_from_version_0() {
+        let state = store().load(r#"{"since":"2024-05-01"}"#).unwrap();
+        assert_eq!(state.cursor.as_deref(), Some("2024-05-01"));
+        assert!(state.seen.is_empty());
+
+        // Not exactly a version number and data, so still legacy data
+        let state = store()
+            .load(r#"{"version":"v1","data":{},"since":"x"}"#)
+            .unwrap();
+        assert_eq!(state.cursor.as_deref(), Some("x"));
+    }
+
+    #[test]
+    fn migrations_are_chained_in_version_order() {
+        let state = store()
+            .load(r#"{"version":1,"data":{"cursor":"c1"}}"#)
+            .unwrap();
+        assert_eq!(
+            state,
+            State {
+                cursor: Some("c1".to_string()),
+                seen: Vec::new(),
+            }
+        );
+
+        let err = TriggerStore::<State>::new(1)
+            .migration(0, |_| {
+                Err(AppError {
+                    code: ErrorCode::Other,
+                    message: "cannot migrate".to_string(),
+                })
+            })
+            .load(r#"{"since":"x"}"#)
+            .unwrap_err();
+        assert_eq!(err.message, "cannot migrate");
+    }
+
+    #[test]
+    fn missing_migrations_are_misconfigured() {
+        let store = TriggerStore::<State>::new(2).migration(0, |old| Ok(old));
+        let err = store.load(r#"{"since":"x"}"#).unwrap_err();
+
+        assert!(matches!(err.code, ErrorCode::Misconfigured));
+        assert_eq!(err.message, "No trigger store migration from version 1");
+    }
+
+    #[test]
+    fn newer_versions_are_misconfigured() {
+        let err = store()
+            .load(r#"{"version":3,"data":{"cursor":"c1"}}"#)
+            .unwrap_err();
+
+        assert!(matches!(err.code, ErrorCode::Misconfigured));
+        assert_eq!(
+            err.message,
+            "Trigger store has version 3, newer than the supported version 2"
+        );
+    }
+
+    #[test]
+    fn unreadable_data_is_reported_with_its_path() {
+        let err = store()
+            .load(r#"{"version":2,"data":{"cursor":"c1","seen":[1,"two"]}}"#)
+            .unwrap_err();
+        assert!(matches!(err.code, ErrorCode::InternalError));
+        assert!(
+            err.message
+                .starts_with("Failed to read trigger store at seen[1]: ")
+        );
+
+        assert!(store().load("{").is_err());
+    }
+
+    #[test]
+    fn stores_over_64_kb_are_not_saved() {
+        let state = State {
+            cursor: Some("x".repeat(MAX_STORE_BYTES)),
+            seen: Vec::new(),
+        };
+
+        let err = store().save(&state).unwrap_err();
+        assert!(matches!(err.code, ErrorCode::InternalError));
+        assert!(err.message.contains("over the 65536 byte limit"));
+        assert!(store().encode(&state).unwrap().len() > MAX_STORE_BYTES);
+
+        let fits = State {
+            cursor: Some("x".repeat(MAX_STORE_BYTES - 100)),
+            seen: Vec::new(),
+        };
+        assert!(store().save(&fits).is_ok());
+    }
+}
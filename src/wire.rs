@@ -0,0 +1,252 @@
+//! HTTP and file plumbing shared by the crate and the local runner
+//!
+//! Only uses `crate::standout::app` and `crate::base64`, which resolve in the
+//! component, under the test harness and in `src/bin/runner`, so the runner
+//! includes this file with `#[path]` instead of keeping its own copy.
+
+use crate::standout::app::file::{FileData, FileError};
+use crate::standout::app::http::{Method, Response};
+
+/// The method as it appears on the wire
+pub fn method_name(method: Method) -> &'static str {
+    match method {
+        Method::Get => "GET",
+        Method::Post => "POST",
+        Method::Put => "PUT",
+        Method::Delete => "DELETE",
+        Method::Patch => "PATCH",
+        Method::Options => "OPTIONS",
+        Method::Head => "HEAD",
+    }
+}
+
+/// Normalize a URL, data URI or base64 string to FileData, fetching URLs
+/// with `fetch`
+#[allow(dead_code)]
+pub fn normalize_file(
+    source: &str,
+    filename: Option<String>,
+    fetch: impl FnOnce(&str) -> Result<Response, FileError>,
+) -> Result<FileData, FileError> {
+    let (bytes, content_type, default_filename) =
+        if source.starts_with("http://") || source.starts_with("https://") {
+            fetched_file(source, fetch(source)?)?
+        } else if let Some(data_uri) = source.strip_prefix("data:") {
+            parse_data_uri(data_uri)?
+        } else {
+            let bytes = crate::base64::decode(source).map_err(FileError::InvalidInput)?;
+            let content_type = sniff(&bytes).to_string();
+            (bytes, content_type, "file".to_string())
+        };
+
+    Ok(FileData {
+        base64: crate::base64::encode(&bytes),
+        content_type,
+        filename: filename.unwrap_or(default_filename),
+    })
+}
+
+/// The bytes, content type and filename of a fetched file
+#[allow(dead_code)]
+fn fetched_file(url: &str, response: Response) -> Result<(Vec<u8>, String, String), FileError> {
+    if !(200..300).contains(&response.status) {
+        return Err(FileError::FetchFailed(format!(
+            "{} returned status {}",
+            url, response.status
+        )));
+    }
+
+    let bytes = response
+        .body_bytes
+        .unwrap_or_else(|| response.body.into_bytes());
+    let content_type = response
+        .headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("Content-Type"))
+        .map(|(_, value)| {
+            value
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        })
+        .unwrap_or_else(|| sniff(&bytes).to_string());
+    let filename = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|path| path.rsplit('/').next())
+        .filter(|name| !name.is_empty() && !name.contains(':'))
+        .unwrap_or("file")
+        .to_string();
+
+    Ok((bytes, content_type, filename))
+}
+
+#[allow(dead_code)]
+fn parse_data_uri(data_uri: &str) -> Result<(Vec<u8>, String, String), FileError> {
+    let (meta, data) = data_uri
+        .split_once(',')
+        .ok_or_else(|| FileError::InvalidInput("Data URI without ','".to_string()))?;
+
+    let (media_type, is_base64) = match meta.strip_suffix(";base64") {
+        Some(media_type) => (media_type, true),
+        None => (meta, false),
+    };
+
+    let bytes = if is_base64 {
+        crate::base64::decode(data).map_err(FileError::InvalidInput)?
+    } else {
+        urlencoding::decode_binary(data.as_bytes()).into_owned()
+    };
+    let content_type = match media_type.split(';').next().unwrap_or_default() {
+        "" => sniff(&bytes).to_string(),
+        media_type => media_type.to_string(),
+    };
+
+    Ok((bytes, content_type, "file".to_string()))
+}
+
+/// Guess the content type from the first bytes
+#[allow(dead_code)]
+fn sniff(bytes: &[u8]) -> &'static str {
+    match bytes {
+        [b'%', b'P', b'D', b'F', ..] => "application/pdf",
+        [0x89, b'P', b'N', b'G', ..] => "image/png",
+        [0xFF, 0xD8, 0xFF, ..] => "image/jpeg",
+        [b'G', b'I', b'F', b'8', ..] => "image/gif",
+        [b'P', b'K', 0x03, 0x04, ..] => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+/// An agent that returns error statuses as responses
+#[cfg(any(feature = "runner", feature = "cassette-record"))]
+#[allow(dead_code)]
+pub fn agent(timeout: Option<std::time::Duration>) -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .timeout_global(timeout)
+        .build()
+        .into()
+}
+
+/// Send a request for real, keeping non-UTF-8 bodies in `body_bytes`
+#[cfg(any(feature = "runner", feature = "cassette-record"))]
+#[allow(dead_code)]
+pub fn send(
+    agent: &ureq::Agent,
+    method: Method,
+    url: &str,
+    headers: &[(String, String)],
+    body: Vec<u8>,
+) -> Result<Response, ureq::Error> {
+    let mut request = ureq::http::Request::builder()
+        .method(method_name(method))
+        .uri(url);
+    for (key, value) in headers {
+        request = request.header(key, value);
+    }
+    let request = request.body(body).map_err(ureq::Error::Http)?;
+
+    let mut response = agent.run(request)?;
+    let headers = response
+        .headers()
+        .iter()
+        .map(|(key, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            (key.to_string(), value)
+        })
+        .collect();
+    let bytes = response
+        .body_mut()
+        .with_config()
+        .limit(u64::MAX)
+        .read_to_vec()?;
+
+    let (body, body_bytes) = match String::from_utf8(bytes) {
+        Ok(body) => (body, None),
+        Err(err) => {
+            let bytes = err.into_bytes();
+            (String::from_utf8_lossy(&bytes).into_owned(), Some(bytes))
+        }
+    };
+
+    Ok(Response {
+        status: response.status().as_u16(),
+        headers,
+        body,
+        body_bytes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_fetch(url: &str) -> Result<Response, FileError> {
+        panic!("unexpected fetch of {}", url)
+    }
+
+    #[test]
+    fn data_uris_and_base64_are_decoded() {
+        let file = normalize_file(
+            "data:text/plain;charset=utf-8,hello%20world",
+            None,
+            no_fetch,
+        )
+        .unwrap();
+        assert_eq!(file.base64, crate::base64::encode(b"hello world"));
+        assert_eq!(file.content_type, "text/plain");
+        assert_eq!(file.filename, "file");
+
+        let pdf = crate::base64::encode(b"%PDF-1.7 ...");
+        let file = normalize_file(&format!("data:;base64,{}", pdf), None, no_fetch).unwrap();
+        assert_eq!(file.content_type, "application/pdf");
+
+        let png = crate::base64::encode(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A]);
+        let file = normalize_file(&png, Some("logo.png".to_string()), no_fetch).unwrap();
+        assert_eq!(file.content_type, "image/png");
+        assert_eq!(file.filename, "logo.png");
+
+        assert!(matches!(
+            normalize_file("data:text/plain", None, no_fetch),
+            Err(FileError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn fetched_files_take_their_name_and_type_from_the_response() {
+        let response = |status, headers: &[(&str, &str)]| Response {
+            status,
+            headers: headers
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            body: String::new(),
+            body_bytes: Some(vec![0xFF, 0xD8, 0xFF, 0xE0]),
+        };
+
+        let file = normalize_file(
+            "https://cdn.example.com/a/photo.jpg?size=large",
+            None,
+            |_| Ok(response(200, &[])),
+        )
+        .unwrap();
+        assert_eq!(file.filename, "photo.jpg");
+        assert_eq!(file.content_type, "image/jpeg");
+
+        let file = normalize_file("https://cdn.example.com/", None, |_| {
+            Ok(response(200, &[("content-type", "image/webp; q=1")]))
+        })
+        .unwrap();
+        assert_eq!(file.filename, "file");
+        assert_eq!(file.content_type, "image/webp");
+
+        let error = normalize_file("https://cdn.example.com/missing", None, |_| {
+            Ok(response(404, &[]))
+        })
+        .unwrap_err();
+        assert!(matches!(error, FileError::FetchFailed(message) if message.contains("404")));
+    }
+}